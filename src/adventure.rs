@@ -1,19 +1,76 @@
 use crate::book::*;
+use crate::random::Rng;
+use crate::profile::Profile;
+use crate::save::SaveGame;
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::iter::Extend;
-use std::ops::Deref;
+use serde::{Serialize, Deserialize};
 
 pub struct Adventure {
     pub questbook: Questbook,
     pub tags: HashSet<String>,
-    pub inventory: HashSet<String>,
+    pub inventory: HashMap<String, u32>,
     pub statuses: HashSet<String>,
     pub chapter: Option<String>,
     pub scene: Option<String>,
     pub counters: HashMap<String, i32>,
     pub state: AdventureState,
     pub log: Vec<String>,
+    pub rng: Rng,
+    /// Number of decisions made so far.
+    pub turn: u32,
+    /// Turn on which each scheduled consequence is applied.
+    pub scheduled: Vec<(u32, String)>,
+    /// Turn on which each expiring status is removed.
+    pub status_expiry: HashMap<String, u32>,
+    /// IDs of the consequences that only apply once, and have been applied.
+    pub applied_once: HashSet<String>,
+    /// IDs of the chapters the player has been in, in the order they were first reached.
+    pub visited_chapters: Vec<String>,
+    /// ID of the furthest chapter in the story the player has reached.
+    pub furthest_chapter: Option<String>,
+    /// IDs of the decisions made, in order.
+    pub decisions_made: Vec<String>,
+    /// IDs of the achievements earned, in this playthrough or earlier ones.
+    pub achievements: HashSet<String>,
+    /// What happened to the player, in order.
+    pub journal: Vec<JournalEntry>,
+    /// State of each quest that has started.
+    pub quests: HashMap<String, QuestState>,
+    /// Indexes of the hints given in each scene, by scene ID.
+    pub scene_hints: HashMap<String, Vec<usize>>,
+    /// Indexes of the hints given in each chapter, by chapter ID.
+    pub chapter_hints: HashMap<String, Vec<usize>>,
+    /// Values of the variables the player's answers were stored in.
+    pub variables: HashMap<String, String>,
+    /// The character the player created, if the questbook has character creation.
+    pub character: Option<PlayerCharacter>,
+}
+
+/// What the player chose when creating their character.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerCharacter {
+    pub name: Option<String>,
+    /// ID of the option picked for each choice, by choice ID.
+    pub choices: HashMap<String, String>,
+    /// Points put into each counter, by counter ID.
+    pub points: HashMap<String, u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum QuestState {
+    Active,
+    Completed,
+    Failed,
+}
+
+/// Something that happened to the player, as they'd remember it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// ID of the chapter the player was in.
+    pub chapter: Option<String>,
+    pub text: String,
 }
 /*
 pub struct Changes {
@@ -26,59 +83,289 @@ pub struct Changes {
     tag,
 }*/
 
-#[derive(PartialEq)]
+/// Why a chapter, scene, decision, or trigger is or isn't available.
+#[derive(Debug)]
+pub struct Explanation {
+    pub kind: &'static str,
+    pub id: String,
+    /// Notes about where the player is, e.g. a scene not being in the current chapter.
+    pub context: Vec<String>,
+    pub requirements: Vec<RequirementCheck>,
+}
+
+/// The result of checking a single requirement.
+#[derive(Debug)]
+pub struct RequirementCheck {
+    pub requirement: String,
+    pub passed: bool,
+    /// The current state the requirement was checked against.
+    pub current: String,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum AdventureState {
     Init,
     Started,
-    Ended,
+    /// The adventure is over, with the ID of the ending reached if there was one.
+    Ended(Option<String>),
 }
 
 impl Adventure {
     pub fn new(questbook: Questbook) -> Adventure {
+        let rng = match questbook.story.seed {
+            Some(seed) => Rng::new(seed),
+            None => Rng::from_time(),
+        };
+        Adventure::with_rng(questbook, rng)
+    }
+
+    /**
+     * Create an adventure with a fixed seed, overriding the seed of the story.
+     */
+    pub fn with_seed(questbook: Questbook, seed: u64) -> Adventure {
+        Adventure::with_rng(questbook, Rng::new(seed))
+    }
+
+    fn with_rng(questbook: Questbook, rng: Rng) -> Adventure {
         let mut counters: HashMap<String, i32> = HashMap::new();
         for counter in questbook.counters.iter() {
             counters.insert(counter.0.clone(), counter.1.value);
         }
+        let variables = questbook.variables.iter().map(|(id, variable)| (id.clone(), variable.value.clone())).collect();
         Adventure {
-            questbook: questbook,
+            questbook,
             tags: HashSet::new(),
-            inventory: HashSet::new(),
+            inventory: HashMap::new(),
             statuses: HashSet::new(),
             chapter: None,
             scene: None,
             state: AdventureState::Init,
-            counters: counters,
+            counters,
             log: Vec::new(),
+            rng,
+            turn: 0,
+            scheduled: Vec::new(),
+            status_expiry: HashMap::new(),
+            applied_once: HashSet::new(),
+            visited_chapters: Vec::new(),
+            furthest_chapter: None,
+            decisions_made: Vec::new(),
+            achievements: HashSet::new(),
+            journal: Vec::new(),
+            quests: HashMap::new(),
+            scene_hints: HashMap::new(),
+            chapter_hints: HashMap::new(),
+            variables,
+            character: None,
+        }
+    }
+
+    /**
+     * Carry over what was earned in earlier playthroughs: achievements already earned, and
+     * the tags they unlock. Call this before starting the adventure.
+     */
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        self.achievements.extend(profile.achievements.iter().cloned());
+        for tag in profile.unlocks.iter() {
+            self.tags.insert(tag.clone());
+            self.log.push(format!("Tag {} unlocked by an earlier playthrough", tag));
+        }
+    }
+
+    /**
+     * Update quests and achievements after something happened, returning an announcement
+     * for each change.
+     */
+    fn check_progress(&mut self) -> Vec<String> {
+        let mut output = self.check_quests();
+        output.extend(self.check_achievements());
+        output
+    }
+
+    /**
+     * Start, complete or fail the quests whose requirements are met, returning an
+     * announcement for each change.
+     */
+    fn check_quests(&mut self) -> Vec<String> {
+        let mut ids: Vec<&String> = self.questbook.quests.keys().collect();
+        ids.sort();
+        let mut changes = Vec::new();
+        for id in ids {
+            let quest = &self.questbook.quests[id];
+            let mut state = self.quests.get(id).copied();
+            if state.is_none() && self.check_requirements_options(&format!("quest:{}:start", id), &quest.start) {
+                state = Some(QuestState::Active);
+                changes.push((id.clone(), QuestState::Active, format!("New quest: {}", quest.title)));
+            }
+            if state == Some(QuestState::Active) {
+                if self.check_requirements(&format!("quest:{}:complete", id), &quest.complete) {
+                    changes.push((id.clone(), QuestState::Completed, format!("Quest completed: {}", quest.title)));
+                } else if quest.fail.as_ref().is_some_and(|fail| self.check_requirements(&format!("quest:{}:fail", id), fail)) {
+                    changes.push((id.clone(), QuestState::Failed, format!("Quest failed: {}", quest.title)));
+                }
+            }
+        }
+        let mut output = Vec::new();
+        for (id, state, announcement) in changes {
+            self.log.push(format!("Quest {} is now {:?}", id, state));
+            self.quests.insert(id, state);
+            output.push(announcement);
+        }
+        output
+    }
+
+    /**
+     * Earn the achievements whose requirements are met, returning an announcement for each.
+     */
+    fn check_achievements(&mut self) -> Vec<String> {
+        let mut earned: Vec<(&String, &Achievement)> = self.questbook.achievements.iter()
+            .filter(|(id, achievement)| !self.achievements.contains(*id) && self.check_requirements(&format!("achievement:{}", id), &achievement.requirements))
+            .collect();
+        earned.sort_by_key(|(id, _)| *id);
+        let mut output = Vec::new();
+        for (id, achievement) in earned {
+            output.push(format!("Achievement unlocked: {}", achievement.title));
+            self.log.push(format!("Achievement earned: {}", id));
+            self.achievements.insert(id.clone());
+        }
+        output
+    }
+
+    /**
+     * Create the player's character from what they chose, before the adventure starts.
+     * Nothing is applied if any of it isn't allowed by the questbook.
+     */
+    pub fn create_character(&mut self, character: PlayerCharacter) -> Result<(), String> {
+        let creation = self.questbook.story.creation.as_ref().ok_or("This adventure has no character creation")?;
+        if self.state != AdventureState::Init {
+            return Err("The character can only be created before the adventure starts".to_string());
+        }
+        let name = character.name.as_deref().map(str::trim).unwrap_or_default();
+        if creation.name.is_some() && name.is_empty() {
+            return Err("Your character needs a name".to_string());
+        }
+        let mut provides = Vec::new();
+        for choice in creation.choices.iter().flatten() {
+            let picked = character.choices.get(&choice.id).and_then(|id| choice.options.iter().find(|o| &o.id == id));
+            match picked {
+                Some(option) => provides.extend(option.provides.iter().flatten().cloned()),
+                None => {
+                    let options: Vec<&str> = choice.options.iter().map(|o| o.id.as_str()).collect();
+                    return Err(format!("{} Choose one of: {}", choice.question, options.join(", ")));
+                },
+            }
+        }
+        let total = creation.points.as_ref().map(|p| p.total).unwrap_or(0);
+        let too_many = || format!("Only {} points can be distributed", total);
+        let mut spent: u32 = 0;
+        let mut counters = Vec::new();
+        for (counter, points) in character.points.iter() {
+            let allowed = creation.points.as_ref().filter(|p| p.counters.contains(counter));
+            match allowed {
+                None => return Err(format!("Points can't go into {}", counter)),
+                Some(allowed) if allowed.max.is_some_and(|max| *points > max) => {
+                    return Err(format!("At most {} points can go into {}", allowed.max.unwrap(), counter));
+                },
+                Some(_) => {
+                    spent = spent.checked_add(*points).ok_or_else(too_many)?;
+                    let value = i32::try_from(*points).ok()
+                        .and_then(|points| self.counters.get(counter).copied().unwrap_or(0).checked_add(points))
+                        .ok_or_else(|| format!("Too many points for {}", counter))?;
+                    counters.push((counter.clone(), value));
+                },
+            }
+        }
+        if spent > total {
+            return Err(too_many());
+        }
+
+        if let Some(variable) = creation.name.clone() {
+            self.variables.insert(variable, name.to_string());
+        }
+        for provide in provides {
+            self.provide(&provide);
         }
+        self.counters.extend(counters);
+        self.log.push(format!("Character created: {:?}", character));
+        self.character = Some(character);
+        Ok(())
     }
 
-    pub fn start(&mut self) {
+    /**
+     * Start the adventure in the latest chapter available, returning the chapter's heading
+     * and the output of entering the chapter and scene.
+     */
+    pub fn start(&mut self) -> Vec<String> {
+        let mut output = Vec::new();
         self.chapter = self.get_chapter();
-        match self.chapter {
-            Some(_) => {
+        match self.chapter.clone() {
+            Some(chapter) => {
                 self.state = AdventureState::Started;
+                self.reach_chapter(&chapter);
                 self.scene = self.get_scene();
-                self.log.push(format!("Adventure started - Chapter: {} - Scene: {}", self.chapter.as_ref().unwrap_or(&"None".to_string()), self.scene.as_ref().unwrap_or(&"None".to_string())));
+                self.log.push(format!("Adventure started - Chapter: {} - Scene: {}", chapter, self.scene.as_ref().unwrap_or(&"None".to_string())));
+                output.extend(self.chapter_heading(&chapter));
+                output.extend(self.enter_chapter(&chapter));
+                if let Some(scene) = self.scene.clone() {
+                    self.visit_scene(&scene);
+                    output.extend(self.enter_scene(&scene));
+                }
+                output.extend(self.check_quests());
             },
-            None => self.state = AdventureState::Ended,
+            None => self.end(None),
         }
+        output
     }
 
     /**
-     * Returns the latest chapter the player can access.
+     * Returns the latest chapter the player can access. Unless the story allows regression,
+     * this is never before the furthest chapter reached, even if its requirements are no
+     * longer met.
      */
     pub fn get_chapter(&self) -> Option<String> {
         let chapters = self.get_chapters();
-        if chapters.is_empty() {
-            return None;
-        } else {
-            for chapter in self.questbook.story.chapters.iter().rev() {
-                if chapters.contains(chapter) {
-                    return Some(chapter.clone());
-                }
+        let earliest = if self.questbook.story.allow_regression.unwrap_or(false) { None } else { self.furthest_index() };
+        for (index, chapter) in self.questbook.story.chapters.iter().enumerate().rev() {
+            if earliest.is_some_and(|earliest| index < earliest) {
+                break;
+            }
+            if chapters.contains(chapter) {
+                return Some(chapter.clone());
             }
         }
-        return None;
+        earliest.map(|index| self.questbook.story.chapters[index].clone())
+    }
+
+    /**
+     * Position in the story of the furthest chapter reached.
+     */
+    fn furthest_index(&self) -> Option<usize> {
+        let furthest = self.furthest_chapter.as_ref()?;
+        self.questbook.story.chapters.iter().position(|c| c == furthest)
+    }
+
+    /**
+     * Record reaching a chapter, logging chapters that were skipped or gone back from.
+     */
+    fn reach_chapter(&mut self, chapter: &str) {
+        let chapters = &self.questbook.story.chapters;
+        let from = self.chapter.as_ref().and_then(|c| chapters.iter().position(|id| id == c));
+        let to = chapters.iter().position(|id| id == chapter);
+        match (from, to) {
+            (Some(from), Some(to)) if to > from + 1 => {
+                self.log.push(format!("Chapter jump: {} to {}, skipping {}", chapters[from], chapter, chapters[from + 1..to].join(", ")));
+            },
+            (Some(from), Some(to)) if to < from => {
+                self.log.push(format!("Chapter regression: {} back to {}", chapters[from], chapter));
+            },
+            _ => {},
+        }
+        if to.is_some() && (self.furthest_index().is_none() || to > self.furthest_index()) {
+            self.furthest_chapter = Some(chapter.to_string());
+        }
+        if !self.visited_chapters.iter().any(|c| c == chapter) {
+            self.visited_chapters.push(chapter.to_string());
+        }
     }
 
     /**
@@ -91,29 +378,106 @@ impl Adventure {
         }
 
         for (id, chapter) in self.questbook.chapters.iter() {
-            if self.check_requirements_options(&chapter.requirements) {
+            if self.check_requirements_options(&format!("chapter:{}", id), &chapter.requirements) {
                 chapters.insert(id.clone());
             }
         }
         chapters
     }
 
-    pub fn change_chapter(&mut self) {
+    /**
+     * Move to the latest chapter available, if it changed, returning the output of leaving
+     * the scene, then the chapter's heading and the output of entering the chapter and its
+     * first scene.
+     */
+    pub fn change_chapter(&mut self) -> Vec<String> {
+        let mut output = Vec::new();
         match self.get_chapter() {
             Some(chapter) => {
                 if self.chapter.eq(&Some(chapter.clone())) {
-                    return;
+                    return output;
                 }
+                output.extend(self.leave_scene());
+                self.reach_chapter(&chapter);
                 self.chapter = Some(chapter.clone());
                 self.log.push(format!("Chapter changed to: {}", chapter));
-                self.change_scenes();
+                output.extend(self.chapter_heading(&chapter));
+                output.extend(self.enter_chapter(&chapter));
+                output.extend(self.change_scenes());
             },
             None => {
+                output.extend(self.leave_scene());
                 self.chapter = None;
                 self.log.push("No more chapters available".to_string());
-                self.state = AdventureState::Ended;
+                self.end(None);
             },
         }
+        output
+    }
+
+    /**
+     * The title and intro of a chapter, shown when the player enters it.
+     */
+    pub fn chapter_heading(&self, chapter: &str) -> Vec<String> {
+        let chapter = match self.questbook.chapters.get(chapter) {
+            Some(chapter) => chapter,
+            None => return Vec::new(),
+        };
+        let mut heading = vec![format!("~ {} ~", chapter.title)];
+        heading.extend(chapter.intro.iter().map(|intro| self.fill_template(intro)));
+        heading
+    }
+
+    /**
+     * Write something that happened in the current chapter to the journal.
+     */
+    fn write_journal(&mut self, text: String) {
+        self.journal.push(JournalEntry {
+            chapter: self.chapter.clone(),
+            text,
+        });
+    }
+
+    fn visit_scene(&mut self, scene: &str) {
+        if let Some(name) = self.questbook.scenes.get(scene).map(|s| s.name.clone()) {
+            self.write_journal(format!("Arrived at {}", name));
+        }
+    }
+
+    /**
+     * Apply the consequences of entering a chapter.
+     */
+    fn enter_chapter(&mut self, chapter: &str) -> Vec<String> {
+        let consequences = self.questbook.chapters.get(chapter).and_then(|c| c.on_enter.clone());
+        self.apply_hook(consequences)
+    }
+
+    /**
+     * Apply the consequences of entering a scene.
+     */
+    fn enter_scene(&mut self, scene: &str) -> Vec<String> {
+        let consequences = self.questbook.scenes.get(scene).and_then(|s| s.on_enter.clone());
+        self.apply_hook(consequences)
+    }
+
+    /**
+     * Leave the current scene, applying the consequences of leaving it.
+     */
+    fn leave_scene(&mut self) -> Vec<String> {
+        let scene = match self.scene.take() {
+            Some(scene) => scene,
+            None => return Vec::new(),
+        };
+        let consequences = self.questbook.scenes.get(&scene).and_then(|s| s.on_exit.clone());
+        self.apply_hook(consequences)
+    }
+
+    fn apply_hook(&mut self, consequences: Option<Vec<String>>) -> Vec<String> {
+        let mut output = Vec::new();
+        for consequence in consequences.into_iter().flatten() {
+            output.extend(self.apply_consequence(&consequence));
+        }
+        output
     }
 
     /**
@@ -124,9 +488,7 @@ impl Adventure {
             return self.scene.clone();
         }
 
-        if self.chapter.is_none() {
-            return None;
-        }
+        self.chapter.as_ref()?;
 
         let scenes = self.get_scenes();
         if scenes.is_empty() {
@@ -138,7 +500,7 @@ impl Adventure {
                 }
             }
         }
-        return None;
+        None
     }
 
     /**
@@ -152,10 +514,8 @@ impl Adventure {
         }
 
         for (id, scene) in self.questbook.scenes.iter() {
-            if self.chapter_contains_scene(self.chapter.as_ref().unwrap(), id) {
-                if self.check_requirements_options(&scene.requirements) {
-                    scenes.insert(id.clone());
-                }
+            if self.chapter_contains_scene(self.chapter.as_ref().unwrap(), id) && self.check_requirements_options(&format!("scene:{}", id), &scene.requirements) {
+                scenes.insert(id.clone());
             }
         }
         scenes
@@ -182,10 +542,27 @@ impl Adventure {
         }
 
         for (id, decision) in self.questbook.decisions.iter() {
-            if self.scene_contains_decision(self.scene.as_ref().unwrap(), id) || self.decision_is_global(id) {
-                if self.check_requirements_options(&decision.requirements) {
-                    decisions.insert(id.clone());
-                }
+            if (self.scene_contains_decision(self.scene.as_ref().unwrap(), id) || self.decision_is_global(id)) && self.check_requirements_options(&format!("decision:{}", id), &decision.requirements) {
+                decisions.insert(id.clone());
+            }
+        }
+        decisions
+    }
+
+    /**
+     * Get the decisions the player can't make yet, but that should be shown to them.
+     */
+    pub fn get_locked_decisions(&self) -> HashSet<String> {
+        let mut decisions = HashSet::new();
+        if self.chapter.is_none() || self.scene.is_none() {
+            return decisions;
+        }
+
+        for (id, decision) in self.questbook.decisions.iter() {
+            if decision.show_when_locked.unwrap_or(false)
+                && (self.scene_contains_decision(self.scene.as_ref().unwrap(), id) || self.decision_is_global(id))
+                && !self.check_requirements_options(&format!("decision:{}", id), &decision.requirements) {
+                decisions.insert(id.clone());
             }
         }
         decisions
@@ -219,53 +596,320 @@ impl Adventure {
         false
     }
 
-    pub fn change_scenes(&mut self) {
+    pub fn change_scenes(&mut self) -> Vec<String> {
         let scenes = self.get_scenes();
         if scenes.is_empty() {
-            self.scene = None;
+            let output = self.leave_scene();
             self.log.push("No more scenes available".to_string());
-            self.state = AdventureState::Ended;
-            return;
+            self.end(None);
+            return output;
         }
-        self.change_scene(scenes.iter().next().unwrap(), false);
+        self.change_scene(scenes.iter().next().unwrap(), false)
     }
 
-    pub fn change_scene(&mut self, scene: &str, check_triggers: bool) {
-        if !self.get_scenes().contains(scene) {
-            return;
+    /**
+     * Move to a scene in the current chapter, returning the output of leaving the current
+     * scene and entering the new one.
+     */
+    pub fn change_scene(&mut self, scene: &str, check_triggers: bool) -> Vec<String> {
+        let mut output = Vec::new();
+        if !self.get_scenes().contains(scene) || self.scene.as_deref() == Some(scene) {
+            return output;
         }
+        output.extend(self.leave_scene());
         self.scene = Some(scene.to_string());
         self.log.push(format!("Scene changed to: {}", scene));
+        self.visit_scene(scene);
+        output.extend(self.enter_scene(scene));
         if check_triggers {
             self.check_triggers();
+            output.extend(self.check_progress());
         }
+        output
     }
 
     /**
      * Make a decision and apply consequences.
      */
     pub fn make_decision(&mut self, decision: &str) -> Vec<String> {
+        self.decide(decision, "")
+    }
+
+    /**
+     * The question asked when a decision is made, if the player answers it in their own
+     * words.
+     */
+    pub fn prompt(&self, decision: &str) -> Option<&String> {
+        self.questbook.decisions.get(decision)?.prompt.as_ref().map(|p| &p.question)
+    }
+
+    /**
+     * Make a decision that asks a question, with the player's answer.
+     */
+    pub fn answer(&mut self, decision: &str, answer: &str) -> Vec<String> {
+        self.decide(decision, answer)
+    }
+
+    fn decide(&mut self, decision: &str, answer: &str) -> Vec<String> {
         let mut output = Vec::new();
         if !self.get_decisions().contains(decision) {
             return output;
         }
         self.log.push(format!("Decision made: {}", decision));
-        output.extend(self.questbook.decisions.get(decision).unwrap().describe());
-        for consequence in self.questbook.get_consequences_from_decision(decision) {
-            self.apply_consequence(&consequence);
+        self.decisions_made.push(decision.to_string());
+        let description = self.questbook.decisions.get(decision).unwrap().describe();
+        output.extend(description.iter().map(|text| self.fill_template(text)));
+
+        // Re-roll chance requirements for the next turn.
+        self.rng.next_u64();
+
+        // The decision's consequences happen in the new turn, so durations and schedules
+        // they start count from it.
+        self.turn += 1;
+        self.log.push(format!("Turn {}", self.turn));
+
+        let mut consequences = self.questbook.get_consequences_from_decision(decision);
+        consequences.extend(self.pick_outcome(decision));
+        if let Some((result, check_consequences)) = self.roll_check(decision) {
+            output.push(result);
+            consequences.extend(check_consequences);
+        }
+        consequences.extend(self.check_answer(decision, answer));
+        for consequence in consequences {
+            output.extend(self.apply_consequence(&consequence));
         }
+        output.extend(self.end_turn());
         self.check_triggers();
+        output.extend(self.check_progress());
+        output
+    }
+
+    /**
+     * End the current turn, removing expired statuses and applying scheduled consequences
+     * that are due.
+     */
+    fn end_turn(&mut self) -> Vec<String> {
+        let mut output = Vec::new();
+
+        let expired: Vec<String> = self.status_expiry.iter()
+            .filter(|(_, turn)| **turn <= self.turn)
+            .map(|(status, _)| status.clone())
+            .collect();
+        for status in expired {
+            self.status_expiry.remove(&status);
+            self.statuses.remove(&status);
+            self.log.push(format!("Status {} expired", status));
+        }
+
+        let (due, pending) = self.scheduled.drain(..).partition(|(turn, _)| *turn <= self.turn);
+        self.scheduled = pending;
+        for (_, consequence) in due {
+            output.extend(self.apply_consequence(&consequence));
+        }
         output
     }
 
+    /**
+     * Pick one of the weighted outcomes of a decision, returning its consequences.
+     */
+    fn pick_outcome(&mut self, decision: &str) -> Vec<String> {
+        let outcomes = match self.questbook.decisions.get(decision).and_then(|d| d.outcomes.as_ref()) {
+            Some(outcomes) => outcomes,
+            None => return Vec::new(),
+        };
+        let weights: Vec<u32> = outcomes.iter().map(|o| o.weight).collect();
+        match self.rng.weighted(&weights) {
+            Some(index) => {
+                self.log.push(format!("Outcome {} of {} picked", index + 1, decision));
+                outcomes[index].consequences.clone()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /**
+     * Roll the skill check of a decision, returning the roll breakdown and the consequences
+     * of the result.
+     */
+    fn roll_check(&mut self, decision: &str) -> Option<(String, Vec<String>)> {
+        let check = self.questbook.decisions.get(decision)?.check.as_ref()?;
+        let rolls = self.rng.roll(&check.dice);
+        let mut total = check.dice.total(&rolls);
+        let mut breakdown = format!("{} {:?}", check.dice, rolls);
+        for counter in check.counters.iter().flatten() {
+            let value = self.counters.get(counter).copied().unwrap_or(0);
+            total = total.saturating_add(value);
+            breakdown.push_str(&format!(" + {} ({})", counter, value));
+        }
+        let success = total >= check.difficulty;
+        let result = format!("Check: {} = {} vs {} - {}", breakdown, total, check.difficulty, if success { "success" } else { "failure" });
+        self.log.push(format!("{} for decision {}", result, decision));
+        let consequences = if success { check.success.clone() } else { check.failure.clone() };
+        Some((result, consequences))
+    }
+
+    /**
+     * Check the answer to the question of a decision, storing it in its variable, and
+     * returning the consequences of the answer.
+     */
+    fn check_answer(&mut self, decision: &str, answer: &str) -> Vec<String> {
+        let prompt = match self.questbook.decisions.get(decision).and_then(|d| d.prompt.as_ref()) {
+            Some(prompt) => prompt,
+            None => return Vec::new(),
+        };
+        let answer = answer.trim();
+        let accepted = prompt.accepts(answer);
+        self.log.push(format!("Answer \"{}\" for decision {} {}", answer, decision, if accepted { "accepted" } else { "rejected" }));
+        let consequences = if accepted { prompt.success.clone() } else { prompt.failure.clone() };
+        if let Some(variable) = prompt.variable.clone() {
+            self.variables.insert(variable, answer.to_string());
+        }
+        consequences
+    }
+
+    /**
+     * Number of an item the player holds.
+     */
+    pub fn item_count(&self, item: &str) -> u32 {
+        self.inventory.get(item).copied().unwrap_or(0)
+    }
+
+    /**
+     * Remove a number of an item from an inventory, returning false if there weren't enough.
+     */
+    fn remove_items(inventory: &mut HashMap<String, u32>, item: &str, count: u32) -> bool {
+        match inventory.get_mut(item) {
+            Some(held) if *held >= count => {
+                *held -= count;
+                if *held == 0 {
+                    inventory.remove(item);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /**
+     * Use an item, applying its consequences. Returns `None` if the item can't be used.
+     */
+    pub fn use_item(&mut self, item: &str) -> Option<Vec<String>> {
+        if self.item_count(item) == 0 {
+            return None;
+        }
+        let definition = self.questbook.items.get(item)?;
+        let consequences = definition.on_use.clone()?;
+        if definition.consumable.unwrap_or(false) {
+            Adventure::remove_items(&mut self.inventory, item, 1);
+        }
+        self.log.push(format!("Item used: {}", item));
+        let mut output = Vec::new();
+        for consequence in consequences {
+            output.extend(self.apply_consequence(&consequence));
+        }
+        self.check_triggers();
+        output.extend(self.check_progress());
+        Some(output)
+    }
+
+    /**
+     * Give the next hint for the current scene, or the current chapter if the scene has
+     * none left, applying its cost. Returns `None` if there are no more hints.
+     */
+    pub fn hint(&mut self) -> Option<Vec<String>> {
+        let scene = self.scene.clone().and_then(|id| {
+            let hints = self.questbook.scenes.get(&id)?.hints.as_ref()?;
+            let index = self.next_hint(&format!("scene:{}", id), hints, self.scene_hints.get(&id))?;
+            Some((id, &hints[index], index))
+        });
+        let (text, cost) = match scene {
+            Some((id, hint, index)) => {
+                let (text, cost) = (hint.text.clone(), hint.cost.clone());
+                self.log.push(format!("Hint {} given for scene {}", index + 1, id));
+                self.scene_hints.entry(id).or_default().push(index);
+                (text, cost)
+            },
+            None => {
+                let id = self.chapter.clone()?;
+                let hints = self.questbook.chapters.get(&id)?.hints.as_ref()?;
+                let index = self.next_hint(&format!("chapter:{}", id), hints, self.chapter_hints.get(&id))?;
+                let hint = &hints[index];
+                let (text, cost) = (hint.text.clone(), hint.cost.clone());
+                self.log.push(format!("Hint {} given for chapter {}", index + 1, id));
+                self.chapter_hints.entry(id).or_default().push(index);
+                (text, cost)
+            },
+        };
+        let mut output = vec![format!("Hint: {}", self.fill_template(&text))];
+        for consequence in cost.into_iter().flatten() {
+            output.extend(self.apply_consequence(&consequence));
+        }
+        self.check_triggers();
+        output.extend(self.check_progress());
+        Some(output)
+    }
+
+    /**
+     * Index of the first hint that hasn't been given yet and whose requirements are met.
+     * Hints whose requirements aren't met yet are kept for later.
+     */
+    fn next_hint(&self, owner: &str, hints: &[Hint], given: Option<&Vec<usize>>) -> Option<usize> {
+        (0..hints.len()).find(|index| {
+            !given.is_some_and(|given| given.contains(index))
+                && self.check_requirements_options(&format!("{}:hint:{}", owner, index), &hints[*index].requirements)
+        })
+    }
+
+    /**
+     * Drop one of an item. Returns false if the item isn't held or can't be dropped.
+     */
+    pub fn drop_item(&mut self, item: &str) -> bool {
+        let droppable = self.questbook.items.get(item).and_then(|i| i.droppable).unwrap_or(false);
+        if !droppable || !Adventure::remove_items(&mut self.inventory, item, 1) {
+            return false;
+        }
+        self.log.push(format!("Item dropped: {}", item));
+        self.check_triggers();
+        true
+    }
+
+    /**
+     * Combine two items using a recipe from the questbook. Returns `None` if no recipe
+     * matches the items, or the player doesn't hold them.
+     */
+    pub fn combine_items(&mut self, first: &str, second: &str) -> Option<Vec<String>> {
+        let mut wanted = vec![first, second];
+        wanted.sort();
+        let (id, recipe) = self.questbook.recipes.iter().find(|(_, recipe)| {
+            let mut ingredients: Vec<&str> = recipe.ingredients.iter().map(|i| i.as_str()).collect();
+            ingredients.sort();
+            ingredients == wanted
+        })?;
+
+        let mut inventory = self.inventory.clone();
+        if !recipe.ingredients.iter().all(|i| Adventure::remove_items(&mut inventory, i, 1)) {
+            return None;
+        }
+        self.inventory = inventory;
+        self.log.push(format!("Recipe used: {}", id));
+
+        let mut output = Vec::new();
+        for consequence in recipe.consequences.clone() {
+            output.extend(self.apply_consequence(&consequence));
+        }
+        self.check_triggers();
+        output.extend(self.check_progress());
+        Some(output)
+    }
+
     /**
      * Check all triggers to see if any are met.
      */
     fn check_triggers(&mut self) {
         let mut consequences = Vec::new();
         for (id, trigger) in self.questbook.triggers.iter() {
-            if self.check_requirements(&trigger.requirements) {
-                consequences.push(id.clone());
+            if self.check_requirements(&format!("trigger:{}", id), &trigger.requirements) {
+                consequences.extend(trigger.consequences.iter().cloned());
             }
         }
         if consequences.is_empty() {
@@ -276,6 +920,316 @@ impl Adventure {
         }
     }
 
+    /**
+     * Give the player an item, ignoring requirements. Returns false if there's no such item.
+     */
+    pub fn give_item(&mut self, item: &str) -> bool {
+        if !self.questbook.items.contains_key(item) {
+            return false;
+        }
+        *self.inventory.entry(item.to_string()).or_insert(0) += 1;
+        self.log.push(format!("DEV - Item {} given", item));
+        true
+    }
+
+    /**
+     * Take one of an item from the player. Returns false if they don't have it.
+     */
+    pub fn take_item(&mut self, item: &str) -> bool {
+        if !Adventure::remove_items(&mut self.inventory, item, 1) {
+            return false;
+        }
+        self.log.push(format!("DEV - Item {} taken", item));
+        true
+    }
+
+    /**
+     * Add a tag. Returns false if the ID belongs to an item or status.
+     */
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.questbook.items.contains_key(tag) || self.questbook.statuses.contains_key(tag) {
+            return false;
+        }
+        self.tags.insert(tag.to_string());
+        self.log.push(format!("DEV - Tag {} added", tag));
+        true
+    }
+
+    /**
+     * Remove a tag. Returns false if the tag isn't set.
+     */
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        if !self.tags.remove(tag) {
+            return false;
+        }
+        self.log.push(format!("DEV - Tag {} removed", tag));
+        true
+    }
+
+    /**
+     * Set a counter to a value. Returns false if there's no such counter.
+     */
+    pub fn set_counter(&mut self, counter: &str, value: i32) -> bool {
+        match self.counters.get_mut(counter) {
+            Some(counter_value) => {
+                *counter_value = value;
+                self.log.push(format!("DEV - Counter {} set to: {}", counter, value));
+                true
+            },
+            None => false,
+        }
+    }
+
+    /**
+     * Add a status, starting its duration if it has one. Returns false if there's no such
+     * status.
+     */
+    pub fn add_status(&mut self, status: &str) -> bool {
+        let duration = match self.questbook.statuses.get(status) {
+            Some(definition) => definition.duration,
+            None => return false,
+        };
+        self.statuses.insert(status.to_string());
+        if let Some(duration) = duration {
+            self.status_expiry.insert(status.to_string(), self.turn.saturating_add(duration));
+        }
+        self.log.push(format!("DEV - Status {} added", status));
+        true
+    }
+
+    /**
+     * Remove a status. Returns false if the player doesn't have it.
+     */
+    pub fn remove_status(&mut self, status: &str) -> bool {
+        if !self.statuses.remove(status) {
+            return false;
+        }
+        self.status_expiry.remove(status);
+        self.log.push(format!("DEV - Status {} removed", status));
+        true
+    }
+
+    /**
+     * Move the player to a chapter and scene, ignoring requirements. Returns false if the
+     * scene isn't in the chapter.
+     */
+    pub fn goto(&mut self, chapter: &str, scene: &str) -> bool {
+        if !self.chapter_contains_scene(chapter, scene) || !self.questbook.scenes.contains_key(scene) {
+            return false;
+        }
+        // Let the player move back as well as forward.
+        self.furthest_chapter = None;
+        self.reach_chapter(chapter);
+        self.chapter = Some(chapter.to_string());
+        self.scene = Some(scene.to_string());
+        self.state = AdventureState::Started;
+        self.log.push(format!("DEV - Moved to chapter: {} - scene: {}", chapter, scene));
+        true
+    }
+
+    /**
+     * Replace the questbook, keeping the current state. State that refers to something
+     * that no longer exists is dropped, and a description of each change is returned.
+     */
+    pub fn rebind(&mut self, questbook: Questbook) -> Vec<String> {
+        self.questbook = questbook;
+        self.log.push("Questbook reloaded".to_string());
+        self.prune()
+    }
+
+    /**
+     * Store the state of the adventure for a save file.
+     */
+    pub fn save(&self) -> SaveGame {
+        SaveGame {
+            title: self.questbook.title().clone(),
+            tags: self.tags.clone(),
+            inventory: self.inventory.clone(),
+            statuses: self.statuses.clone(),
+            chapter: self.chapter.clone(),
+            scene: self.scene.clone(),
+            counters: self.counters.clone(),
+            log: self.log.clone(),
+            rng: self.rng,
+            turn: self.turn,
+            scheduled: self.scheduled.clone(),
+            status_expiry: self.status_expiry.clone(),
+            applied_once: self.applied_once.clone(),
+            visited_chapters: self.visited_chapters.clone(),
+            furthest_chapter: self.furthest_chapter.clone(),
+            decisions_made: self.decisions_made.clone(),
+            ending: self.ending().cloned(),
+            achievements: self.achievements.clone(),
+            journal: self.journal.clone(),
+            quests: self.quests.clone(),
+            scene_hints: self.scene_hints.clone(),
+            chapter_hints: self.chapter_hints.clone(),
+            variables: self.variables.clone(),
+            character: self.character.clone(),
+        }
+    }
+
+    /**
+     * Restore the state of the adventure from a save file. State that refers to something
+     * that doesn't exist in the questbook is dropped, and a description of each change is
+     * returned.
+     */
+    pub fn restore(&mut self, save: SaveGame) -> Vec<String> {
+        let mut dropped = Vec::new();
+        if &save.title != self.questbook.title() {
+            dropped.push(format!("Save is from {}", save.title));
+        }
+        self.tags = save.tags;
+        self.inventory = save.inventory;
+        self.statuses = save.statuses;
+        self.chapter = save.chapter;
+        self.scene = save.scene;
+        self.counters = save.counters;
+        self.log = save.log;
+        self.rng = save.rng;
+        self.turn = save.turn;
+        self.scheduled = save.scheduled;
+        self.status_expiry = save.status_expiry;
+        self.applied_once = save.applied_once;
+        self.visited_chapters = save.visited_chapters;
+        self.furthest_chapter = save.furthest_chapter;
+        self.decisions_made = save.decisions_made;
+        self.achievements.extend(save.achievements);
+        self.journal = save.journal;
+        self.quests = save.quests;
+        self.scene_hints = save.scene_hints;
+        self.chapter_hints = save.chapter_hints;
+        self.variables = save.variables;
+        self.character = save.character;
+        self.log.push("Save restored".to_string());
+        self.state = AdventureState::Started;
+        dropped.extend(self.prune());
+        if let Some(ending) = save.ending.filter(|e| self.questbook.endings.contains_key(e)) {
+            self.state = AdventureState::Ended(Some(ending));
+        }
+        dropped
+    }
+
+    /**
+     * Drop state that refers to something that isn't in the questbook, returning a
+     * description of each change.
+     */
+    fn prune(&mut self) -> Vec<String> {
+        let mut dropped = Vec::new();
+
+        let items: Vec<String> = self.inventory.keys().filter(|i| !self.questbook.items.contains_key(*i)).cloned().collect();
+        for item in items {
+            self.inventory.remove(&item);
+            dropped.push(format!("Item {} no longer exists, removed from inventory", item));
+        }
+        let statuses: Vec<String> = self.statuses.iter().filter(|s| !self.questbook.statuses.contains_key(*s)).cloned().collect();
+        for status in statuses {
+            self.statuses.remove(&status);
+            self.status_expiry.remove(&status);
+            dropped.push(format!("Status {} no longer exists, removed", status));
+        }
+        let counters: Vec<String> = self.counters.keys().filter(|c| !self.questbook.counters.contains_key(*c)).cloned().collect();
+        for counter in counters {
+            self.counters.remove(&counter);
+            dropped.push(format!("Counter {} no longer exists, removed", counter));
+        }
+        for (id, counter) in self.questbook.counters.iter() {
+            if !self.counters.contains_key(id) {
+                self.counters.insert(id.clone(), counter.value);
+                dropped.push(format!("Counter {} is new, set to: {}", id, counter.value));
+            }
+        }
+        let variables: Vec<String> = self.variables.keys().filter(|v| !self.questbook.variables.contains_key(*v)).cloned().collect();
+        for variable in variables {
+            self.variables.remove(&variable);
+            dropped.push(format!("Variable {} no longer exists, removed", variable));
+        }
+        for (id, variable) in self.questbook.variables.iter() {
+            if !self.variables.contains_key(id) {
+                self.variables.insert(id.clone(), variable.value.clone());
+                dropped.push(format!("Variable {} is new, set to: {}", id, variable.value));
+            }
+        }
+        let questbook = &self.questbook;
+        self.scheduled.retain(|(_, consequence)| {
+            let exists = questbook.consequences.contains_key(consequence);
+            if !exists {
+                dropped.push(format!("Scheduled consequence {} no longer exists, cancelled", consequence));
+            }
+            exists
+        });
+        self.applied_once.retain(|consequence| questbook.consequences.contains_key(consequence));
+        self.visited_chapters.retain(|chapter| questbook.story.chapters.contains(chapter));
+        self.achievements.retain(|achievement| questbook.achievements.contains_key(achievement));
+        self.quests.retain(|quest, _| questbook.quests.contains_key(quest));
+        self.scene_hints.retain(|scene, _| questbook.scenes.contains_key(scene));
+        self.chapter_hints.retain(|chapter, _| questbook.chapters.contains_key(chapter));
+        if self.furthest_index().is_none() {
+            self.furthest_chapter = self.chapter.clone().filter(|c| self.questbook.story.chapters.contains(c));
+        }
+
+        if let Some(chapter) = self.chapter.clone() {
+            if !self.questbook.chapters.contains_key(&chapter) {
+                dropped.push(format!("Chapter {} no longer exists", chapter));
+                self.chapter = None;
+                self.scene = None;
+            }
+        }
+        if let (Some(chapter), Some(scene)) = (self.chapter.clone(), self.scene.clone()) {
+            if !self.questbook.scenes.contains_key(&scene) || !self.chapter_contains_scene(&chapter, &scene) {
+                dropped.push(format!("Scene {} no longer exists in chapter {}", scene, chapter));
+                self.scene = None;
+            }
+        }
+        // Only a game in progress needs somewhere to be; one that hasn't started or has
+        // already ended keeps its state.
+        if self.state == AdventureState::Started {
+            if self.chapter.is_none() {
+                if let Some(chapter) = self.get_chapter() {
+                    self.reach_chapter(&chapter);
+                    self.chapter = Some(chapter);
+                }
+            }
+            if self.scene.is_none() {
+                self.scene = self.get_scene();
+            }
+            if self.scene.is_none() {
+                self.state = AdventureState::Ended(None);
+            }
+        }
+
+        self.log.extend(dropped.iter().cloned());
+        dropped
+    }
+
+    /**
+     * Apply the consequences of a trigger, ignoring its requirements. Returns `None` if
+     * there's no such trigger.
+     */
+    pub fn fire_trigger(&mut self, trigger: &str) -> Option<Vec<String>> {
+        let consequences = self.questbook.triggers.get(trigger)?.consequences.clone();
+        self.log.push(format!("DEV - Trigger {} fired", trigger));
+        let mut output = Vec::new();
+        for consequence in consequences {
+            output.extend(self.apply_consequence(&consequence));
+        }
+        self.check_triggers();
+        Some(output)
+    }
+
+    /**
+     * Apply a consequence directly. Returns `None` if there's no such consequence.
+     */
+    pub fn apply(&mut self, consequence: &str) -> Option<Vec<String>> {
+        if !self.questbook.consequences.contains_key(consequence) {
+            return None;
+        }
+        self.log.push(format!("DEV - Consequence {} applied", consequence));
+        let output = self.apply_consequence(consequence);
+        self.check_triggers();
+        Some(output)
+    }
+
     /**
      * Apply a consequence.
      */
@@ -284,33 +1238,83 @@ impl Adventure {
         let mut output = Vec::new();
 
         // Apply consequences.
-        let consequence = self.questbook.consequences.get(consequence).unwrap();
+        let id = consequence;
+        let consequence = match self.questbook.consequences.get(id) {
+            Some(consequence) => consequence,
+            None => {
+                self.log.push(format!("Consequence {} doesn't exist, skipped", id));
+                return output;
+            },
+        };
+        if consequence.once.unwrap_or(false) && !self.applied_once.insert(id.to_string()) {
+            self.log.push(format!("Consequence {} already applied once, skipped", id));
+            return output;
+        }
+
+        // Set variables first, so the conclusion can show their new value.
+        if let Some((variable, value)) = &consequence.variable {
+            let value = self.fill_template(value);
+            self.log.push(format!("Variable {} set to: {}", variable, value));
+            self.variables.insert(variable.clone(), value);
+        }
+
+        output.extend(consequence.describe().iter().map(|text| self.fill_template(text)));
+        let mut journal = Vec::new();
+        if !consequence.conclusion.trim().is_empty() {
+            journal.push(self.fill_template(&consequence.conclusion));
+        }
+
+        // Reset counters to their initial value.
+        if let Some(reset) = &consequence.reset {
+            for counter in reset {
+                if let Some(initial) = self.questbook.counters.get(counter) {
+                    self.counters.insert(counter.clone(), initial.value);
+                    self.log.push(format!("Counter {} reset to: {}", counter, initial.value));
+                }
+            }
+        }
+
+        // Clear entire categories of state.
+        if let Some(clear) = &consequence.clear {
+            for category in clear {
+                match category {
+                    StateCategory::Inventory => self.inventory.clear(),
+                    StateCategory::Statuses => {
+                        self.statuses.clear();
+                        self.status_expiry.clear();
+                    },
+                    StateCategory::Tags => self.tags.clear(),
+                    StateCategory::Counters => {
+                        for (id, counter) in self.questbook.counters.iter() {
+                            self.counters.insert(id.clone(), counter.value);
+                        }
+                    },
+                }
+                self.log.push(format!("Cleared {:?}", category));
+            }
+        }
 
         // Provide items, statuses, or tags.
-        if let Some(provides) = &consequence.provides {
+        if let Some(provides) = consequence.provides.clone() {
             for provide in provides {
-                if self.questbook.items.contains_key(provide) {
-                    self.inventory.insert(provide.clone());
-                    self.log.push(format!("Item {} added", provide));
-                } else if self.questbook.statuses.contains_key(provide) {
-                    self.statuses.insert(provide.clone());
-                    self.log.push(format!("Status {} added", provide));
-                } else {
-                    self.tags.insert(provide.clone());
-                    self.log.push(format!("Tag {} added", provide));
-                }
+                journal.extend(self.provide(&provide));
             }
         }
+        let consequence = &self.questbook.consequences[id];
 
         // Remove items, statuses, or tags.
         if let Some(costs) = &consequence.costs {
             for cost in costs {
                 if self.questbook.items.contains_key(cost) {
-                    self.inventory.remove(cost);
+                    Adventure::remove_items(&mut self.inventory, cost, 1);
                     self.log.push(format!("Item {} removed", cost));
-                } else {
+                } else if self.questbook.statuses.contains_key(cost) {
                     self.statuses.remove(cost);
+                    self.status_expiry.remove(cost);
                     self.log.push(format!("Status {} removed", cost));
+                } else {
+                    self.tags.remove(cost);
+                    self.log.push(format!("Tag {} removed", cost));
                 }
             }
         }
@@ -323,51 +1327,639 @@ impl Adventure {
             }
         }
 
-        if let Some(scene) = &consequence.scene.clone() {
-            self.change_scene(scene, false);
+        // Roll dice to change counters.
+        if let Some((counter, dice)) = &consequence.roll {
+            let rolls = self.rng.roll(dice);
+            let total = dice.total(&rolls);
+            if let Some(counter_value) = self.counters.get_mut(counter) {
+                *counter_value = counter_value.saturating_add(total);
+                self.log.push(format!("Rolled {} {:?} for counter {}, changed to: {}", dice, rolls, counter, counter_value));
+                output.push(format!("Rolled {}: {} ({} {:+})", dice, total, counter, total));
+            }
+        }
+
+        // Schedule consequences for later turns.
+        if let Some(schedule) = &consequence.schedule {
+            for (scheduled, turns) in schedule {
+                let turn = self.turn.saturating_add(*turns);
+                self.scheduled.push((turn, scheduled.clone()));
+                self.log.push(format!("Consequence {} scheduled for turn {}", scheduled, turn));
+            }
         }
 
-        self.change_chapter();
+        let ending = consequence.ending.clone();
+        let scene = consequence.scene.clone();
+        for text in journal {
+            self.write_journal(text);
+        }
+        if let Some(scene) = &scene {
+            output.extend(self.change_scene(scene, false));
+        }
+
+        output.extend(self.change_chapter());
+
+        if ending.is_some() {
+            self.end(ending);
+        }
         output
     }
 
     /**
-     * Check if requirements are met of in an option.
+     * End the adventure. An ending that was already reached isn't replaced.
      */
-    fn check_requirements_options(&self, req_opt: &Option<Vec<Requirement>>) -> bool {
+    fn end(&mut self, ending: Option<String>) {
+        if matches!(self.state, AdventureState::Ended(Some(_))) {
+            return;
+        }
+        match &ending {
+            Some(ending) => self.log.push(format!("Ending reached: {}", ending)),
+            None => self.log.push("Adventure ended".to_string()),
+        }
+        self.state = AdventureState::Ended(ending);
+    }
+
+    /**
+     * ID of the ending reached, if the adventure is over and ended with one.
+     */
+    pub fn ending(&self) -> Option<&String> {
+        match &self.state {
+            AdventureState::Ended(ending) => ending.as_ref(),
+            _ => None,
+        }
+    }
+
+    /**
+     * Check if requirements are met of in an option. `owner` is what the requirements are
+     * for, like `decision:open_door`.
+     */
+    fn check_requirements_options(&self, owner: &str, req_opt: &Option<Vec<Requirement>>) -> bool {
         match req_opt {
             None => true,
-            Some(req) => self.check_requirements(req),
+            Some(req) => self.check_requirements(owner, req),
         }
     }
 
     /**
      * Given requirements, see if they're met.
      */
-    fn check_requirements(&self, req: &Vec<Requirement>) -> bool {
-        req.iter().all(|r|
-            match r {
-                Requirement::Require(val) => self.inventory.contains(val) || self.statuses.contains(val) || self.tags.contains(val),
-                Requirement::Refuse(val) => !(self.inventory.contains(val) || self.statuses.contains(val) || self.tags.contains(val)),
-                Requirement::CounterEqual(val, count) => {
-                    match self.counters.get(val) {
-                        Some(value) => value == count,
-                        None => false,
-                    }
-                },
-                Requirement::CounterLessThan(val, count) => {
-                    match self.counters.get(val) {
-                        Some(value) => value < count,
-                        None => false,
-                    }
-                },
-                Requirement::CounterGreaterThan(val, count) => {
-                    match self.counters.get(val) {
-                        Some(value) => value > count,
-                        None => false,
-                    }
+    fn check_requirements(&self, owner: &str, req: &[Requirement]) -> bool {
+        (0..req.len()).all(|i| self.check_requirement(owner, req, i))
+    }
+
+    /**
+     * Check a single requirement from a list of requirements.
+     */
+    fn check_requirement(&self, owner: &str, req: &[Requirement], i: usize) -> bool {
+        match &req[i] {
+            Requirement::Require(val) => self.has(val),
+            Requirement::RequireCount(val, count) => self.item_count(val) >= *count,
+            Requirement::Refuse(val) => !self.has(val),
+            Requirement::CounterEqual(val, count) => {
+                match self.counters.get(val) {
+                    Some(value) => value == count,
+                    None => false,
+                }
+            },
+            Requirement::CounterLessThan(val, count) => {
+                match self.counters.get(val) {
+                    Some(value) => value < count,
+                    None => false,
+                }
+            },
+            Requirement::CounterGreaterThan(val, count) => {
+                match self.counters.get(val) {
+                    Some(value) => value > count,
+                    None => false,
+                }
+            },
+            Requirement::Chance(percent) => self.chance_roll(owner, req, i) <= *percent,
+            Requirement::TurnEqual(turn) => self.turn == *turn,
+            Requirement::TurnLessThan(turn) => self.turn < *turn,
+            Requirement::TurnGreaterThan(turn) => self.turn > *turn,
+            Requirement::VariableEqual(val, expected) => {
+                self.variables.get(val).is_some_and(|value| value.to_lowercase() == expected.to_lowercase())
+            },
+            Requirement::VariableIn(val, expected) => {
+                self.variables.get(val).is_some_and(|value| expected.iter().any(|e| value.to_lowercase() == e.to_lowercase()))
+            },
+        }
+    }
+
+    /**
+     * Give the player an item, status, or tag. Returns what to write in the journal, if
+     * anything.
+     */
+    fn provide(&mut self, provide: &str) -> Option<String> {
+        if let Some(item) = self.questbook.items.get(provide) {
+            *self.inventory.entry(provide.to_string()).or_insert(0) += 1;
+            self.log.push(format!("Item {} added", provide));
+            Some(format!("Found {}", item.name))
+        } else if let Some(status) = self.questbook.statuses.get(provide) {
+            self.statuses.insert(provide.to_string());
+            self.log.push(format!("Status {} added", provide));
+            if let Some(duration) = status.duration {
+                self.status_expiry.insert(provide.to_string(), self.turn.saturating_add(duration));
+            }
+            None
+        } else {
+            self.tags.insert(provide.to_string());
+            self.log.push(format!("Tag {} added", provide));
+            None
+        }
+    }
+
+    /**
+     * Check if the player has an item, status, or tag.
+     */
+    fn has(&self, id: &str) -> bool {
+        self.inventory.contains_key(id) || self.statuses.contains(id) || self.tags.contains(id)
+    }
+
+    /**
+     * The roll for a chance requirement this turn. Each owner gets its own roll, even if its
+     * requirements are the same as another's.
+     */
+    fn chance_roll(&self, owner: &str, req: &[Requirement], i: usize) -> u32 {
+        self.rng.peek_percent(&format!("{}{:?}{}", owner, req, i))
+    }
+
+    /**
+     * Describe the current state a requirement is checked against.
+     */
+    fn requirement_state(&self, owner: &str, req: &[Requirement], i: usize) -> String {
+        match &req[i] {
+            Requirement::Require(val) | Requirement::Refuse(val) => {
+                if self.inventory.contains_key(val) {
+                    format!("{} is in the inventory", val)
+                } else if self.statuses.contains(val) {
+                    format!("{} is a current status", val)
+                } else if self.tags.contains(val) {
+                    format!("{} is a current tag", val)
+                } else {
+                    format!("{} is not held", val)
+                }
+            },
+            Requirement::RequireCount(val, _) => format!("{} = {}", val, self.item_count(val)),
+            Requirement::CounterEqual(val, _) | Requirement::CounterLessThan(val, _) | Requirement::CounterGreaterThan(val, _) => {
+                match self.counters.get(val) {
+                    Some(value) => format!("{} = {}", val, value),
+                    None => format!("{} is not a counter", val),
+                }
+            },
+            Requirement::Chance(_) => format!("rolled {}", self.chance_roll(owner, req, i)),
+            Requirement::TurnEqual(_) | Requirement::TurnLessThan(_) | Requirement::TurnGreaterThan(_) => format!("turn = {}", self.turn),
+            Requirement::VariableEqual(val, _) | Requirement::VariableIn(val, _) => {
+                match self.variables.get(val) {
+                    Some(value) => format!("{} = {:?}", val, value),
+                    None => format!("{} is not a variable", val),
+                }
+            },
+        }
+    }
+
+    /**
+     * Fill in a text template, replacing `{id}` with the value of the variable or counter
+     * with that ID. Anything else in braces is left as it is.
+     */
+    pub fn fill_template(&self, text: &str) -> String {
+        let mut filled = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            filled.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            let id = &rest[1..end];
+            match (self.variables.get(id), self.counters.get(id)) {
+                (Some(value), _) => filled.push_str(value),
+                (None, Some(value)) => filled.push_str(&value.to_string()),
+                (None, None) => {
+                    // Keep the brace and look for placeholders after it.
+                    filled.push('{');
+                    rest = &rest[1..];
+                    continue;
                 },
             }
-        )
+            rest = &rest[end + 1..];
+        }
+        filled.push_str(rest);
+        filled
     }
-}
\ No newline at end of file
+
+    /**
+     * Explain whether a chapter, scene, decision, or trigger is available, evaluating each
+     * of its requirements. Returns `None` if there's nothing with that ID.
+     */
+    pub fn explain(&self, id: &str) -> Option<Explanation> {
+        let (kind, requirements, mut context) = if let Some(chapter) = self.questbook.chapters.get(id) {
+            let mut context = Vec::new();
+            let index = self.questbook.story.chapters.iter().position(|c| c == id);
+            if self.chapter.as_deref() == Some(id) {
+                context.push("This is the current chapter".to_string());
+            } else if !self.questbook.story.allow_regression.unwrap_or(false) && index.is_some() && index < self.furthest_index() {
+                context.push("Chapter is before the furthest chapter reached, and the story doesn't go back".to_string());
+            } else if let Some(current) = self.get_chapter() {
+                if current != id && self.check_requirements_options(&format!("chapter:{}", id), &chapter.requirements) {
+                    context.push(format!("Chapter {} comes later in the story and takes priority", current));
+                }
+            }
+            ("chapter", chapter.requirements.as_ref(), context)
+        } else if let Some(scene) = self.questbook.scenes.get(id) {
+            let mut context = Vec::new();
+            match &self.chapter {
+                Some(chapter) if !self.chapter_contains_scene(chapter, id) => context.push(format!("Scene is not in the current chapter {}", chapter)),
+                None => context.push("There is no current chapter".to_string()),
+                _ => {},
+            }
+            ("scene", scene.requirements.as_ref(), context)
+        } else if let Some(decision) = self.questbook.decisions.get(id) {
+            let mut context = Vec::new();
+            if !self.decision_is_global(id) {
+                match &self.scene {
+                    Some(scene) if !self.scene_contains_decision(scene, id) => context.push(format!("Decision is not in the current scene {}", scene)),
+                    None => context.push("There is no current scene".to_string()),
+                    _ => {},
+                }
+            }
+            ("decision", decision.requirements.as_ref(), context)
+        } else if let Some(trigger) = self.questbook.triggers.get(id) {
+            ("trigger", Some(&trigger.requirements), Vec::new())
+        } else {
+            return None;
+        };
+
+        let owner = format!("{}:{}", kind, id);
+        let mut checks = Vec::new();
+        if let Some(req) = requirements {
+            for i in 0..req.len() {
+                checks.push(RequirementCheck {
+                    requirement: format!("{:?}", req[i]).replace('"', ""),
+                    passed: self.check_requirement(&owner, req, i),
+                    current: self.requirement_state(&owner, req, i),
+                });
+            }
+        }
+        if checks.is_empty() {
+            context.push("No requirements".to_string());
+        }
+
+        Some(Explanation {
+            kind,
+            id: id.to_string(),
+            context,
+            requirements: checks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "
+story:
+  title: Test
+  chapters: [start]
+chapters:
+  start:
+    title: Start
+    scenes: [room]
+scenes:
+  room:
+    name: Room
+    background: A room.
+    decisions: [drink, alarm, wait]
+items:
+decisions:
+  drink:
+    decision: Drink.
+    consequences: [poisoned]
+  alarm:
+    decision: Ring the bell.
+    consequences: [alarm]
+  wait:
+    decision: Wait.
+    consequences: [waited]
+characters:
+consequences:
+  poisoned:
+    conclusion: You feel sick.
+    provides: [poisoned]
+  alarm:
+    conclusion: A bell rings.
+    schedule: [[guards, 1]]
+  guards:
+    conclusion: The guards arrive.
+    provides: [guards_here]
+  waited:
+    conclusion: You wait.
+statuses:
+  poisoned:
+    description: Sick.
+    duration: 1
+counters:
+triggers:
+";
+
+    fn new_adventure(book: &str) -> Adventure {
+        Adventure::with_seed(serde_yaml::from_str(book).unwrap(), 1)
+    }
+
+    /**
+     * A new adventure that has started.
+     */
+    fn adventure(book: &str) -> Adventure {
+        let mut adventure = new_adventure(book);
+        adventure.start();
+        adventure
+    }
+
+    #[test]
+    fn decisions_take_a_turn() {
+        let mut adventure = adventure(BOOK);
+        assert_eq!(adventure.turn, 0);
+        adventure.make_decision("wait");
+        adventure.make_decision("wait");
+        assert_eq!(adventure.turn, 2);
+    }
+
+    #[test]
+    fn status_with_duration_one_lasts_a_turn() {
+        let mut adventure = adventure(BOOK);
+        adventure.make_decision("drink");
+        assert!(adventure.statuses.contains("poisoned"));
+        adventure.make_decision("wait");
+        assert!(!adventure.statuses.contains("poisoned"));
+    }
+
+    #[test]
+    fn consequence_scheduled_in_one_turn_applies_after_the_next_decision() {
+        let mut adventure = adventure(BOOK);
+        adventure.make_decision("alarm");
+        assert!(!adventure.tags.contains("guards_here"));
+        let output = adventure.make_decision("wait");
+        assert!(adventure.tags.contains("guards_here"));
+        assert!(output.contains(&"The guards arrive.".to_string()));
+    }
+
+    #[test]
+    fn huge_durations_dont_overflow() {
+        let book = BOOK.replace("duration: 1", "duration: 4294967295");
+        let mut adventure = adventure(&book);
+        adventure.make_decision("drink");
+        adventure.make_decision("wait");
+        assert!(adventure.statuses.contains("poisoned"));
+        assert!(adventure.add_status("poisoned"));
+        assert_eq!(adventure.status_expiry["poisoned"], u32::MAX);
+    }
+
+    #[test]
+    fn huge_schedules_dont_overflow() {
+        let book = BOOK.replace("[[guards, 1]]", "[[guards, 4294967295]]");
+        let mut adventure = adventure(&book);
+        adventure.make_decision("alarm");
+        adventure.make_decision("wait");
+        assert!(!adventure.tags.contains("guards_here"));
+        assert_eq!(adventure.scheduled, vec![(u32::MAX, "guards".to_string())]);
+    }
+
+    #[test]
+    fn checks_with_extreme_counters_dont_overflow() {
+        let book = BOOK
+            .replace("decisions: [drink, alarm, wait]", "decisions: [drink, alarm, wait, lift]")
+            .replace("decisions:\n  drink:", "decisions:
+  lift:
+    decision: Lift the rock.
+    check:
+      dice: 1d20
+      counters: [strength, strength]
+      difficulty: 10
+      success: [waited]
+      failure: [poisoned]
+  drink:");
+        let mut adventure = adventure(&book);
+        adventure.counters.insert("strength".to_string(), i32::MAX);
+        let output = adventure.make_decision("lift");
+        assert!(output[0].ends_with(&format!("= {} vs 10 - success", i32::MAX)), "{}", output[0]);
+        adventure.counters.insert("strength".to_string(), i32::MIN);
+        let output = adventure.make_decision("lift");
+        assert!(output[0].ends_with(&format!("= {} vs 10 - failure", i32::MIN)), "{}", output[0]);
+    }
+
+    #[test]
+    fn rolls_on_extreme_counters_dont_overflow() {
+        let book = BOOK.replace("    conclusion: You wait.\n", "    conclusion: You wait.\n    roll: [luck, 1d6]\n");
+        let mut lucky = adventure(&book);
+        lucky.counters.insert("luck".to_string(), i32::MAX);
+        lucky.make_decision("wait");
+        assert_eq!(lucky.counters["luck"], i32::MAX);
+        let mut unlucky = adventure(&book.replace("1d6", "-1d6"));
+        unlucky.counters.insert("luck".to_string(), i32::MIN);
+        unlucky.make_decision("wait");
+        assert_eq!(unlucky.counters["luck"], i32::MIN);
+    }
+
+    #[test]
+    fn chances_are_rolled_separately_for_each_decision() {
+        let book = BOOK.replace("  drink:\n", "  drink:\n    requirements:\n      - !Chance 50\n")
+            .replace("  alarm:\n    decision", "  alarm:\n    requirements:\n      - !Chance 50\n    decision");
+        let differ = (0..20).any(|seed| {
+            let mut adventure = Adventure::with_seed(serde_yaml::from_str(&book).unwrap(), seed);
+            adventure.start();
+            let decisions = adventure.get_decisions();
+            decisions.contains("drink") != decisions.contains("alarm")
+        });
+        assert!(differ);
+    }
+
+    #[test]
+    fn hints_wait_until_their_requirements_are_met() {
+        let book = BOOK.replace("    decisions: [drink, alarm, wait]\n", "    decisions: [drink, alarm, wait]
+    hints:
+      - text: Don't drink it.
+        requirements:
+          - !Require poisoned
+      - text: Try waiting.
+");
+        let mut adventure = adventure(&book);
+        assert_eq!(adventure.hint().unwrap()[0], "Hint: Try waiting.");
+        assert!(adventure.hint().is_none());
+        adventure.make_decision("drink");
+        assert_eq!(adventure.hint().unwrap()[0], "Hint: Don't drink it.");
+        assert!(adventure.hint().is_none());
+    }
+
+    #[test]
+    fn chapter_heading_comes_before_entering_it() {
+        let book = BOOK
+            .replace("  chapters: [start]\n", "  chapters: [start, sick]\n")
+            .replace("scenes:\n  room:", "  sick:
+    title: Sick
+    intro: You don't feel well.
+    scenes: [room]
+    requirements:
+      - !Require poisoned
+    on_enter: [waited]
+scenes:
+  room:");
+        let mut adventure = adventure(&book);
+        let output = adventure.make_decision("drink");
+        assert_eq!(output, vec!["You feel sick.", "~ Sick ~", "You don't feel well.", "You wait."]);
+    }
+
+    #[test]
+    fn templates_are_filled_with_variables_and_counters() {
+        let mut adventure = new_adventure(BOOK);
+        adventure.variables.insert("name".to_string(), "Ada".to_string());
+        adventure.variables.insert("gold".to_string(), "plenty".to_string());
+        adventure.counters.insert("gold".to_string(), 12);
+        adventure.counters.insert("health".to_string(), -3);
+        assert_eq!(adventure.fill_template("Hello {name}."), "Hello Ada.");
+        assert_eq!(adventure.fill_template("{health} health"), "-3 health");
+        assert_eq!(adventure.fill_template("{gold} gold"), "plenty gold");
+        assert_eq!(adventure.fill_template("{name}{name}"), "AdaAda");
+        assert_eq!(adventure.fill_template("No placeholders"), "No placeholders");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        let mut adventure = new_adventure(BOOK);
+        adventure.variables.insert("name".to_string(), "Ada".to_string());
+        assert_eq!(adventure.fill_template("Hello {nobody}."), "Hello {nobody}.");
+        assert_eq!(adventure.fill_template("{} and {name"), "{} and {name");
+        assert_eq!(adventure.fill_template("{{name}}"), "{Ada}");
+        assert_eq!(adventure.fill_template("{a {name}}"), "{a Ada}");
+        assert_eq!(adventure.fill_template("Ünïcode {name} ✓"), "Ünïcode Ada ✓");
+    }
+
+    #[test]
+    fn reloading_keeps_an_ended_adventure_over() {
+        let book = BOOK
+            .replace("    conclusion: You wait.\n", "    conclusion: You wait.\n    ending: bored\n")
+            .replace("triggers:\n", "triggers:\nendings:\n  bored:\n    title: Bored\n    epilogue: Nothing happened.\n");
+        let mut adventure = adventure(&book);
+        adventure.make_decision("wait");
+        assert_eq!(adventure.state, AdventureState::Ended(Some("bored".to_string())));
+        adventure.rebind(serde_yaml::from_str(&book).unwrap());
+        assert_eq!(adventure.state, AdventureState::Ended(Some("bored".to_string())));
+    }
+
+    #[test]
+    fn reloading_keeps_an_unstarted_adventure_waiting() {
+        let mut adventure = new_adventure(BOOK);
+        adventure.rebind(serde_yaml::from_str(BOOK).unwrap());
+        assert_eq!(adventure.state, AdventureState::Init);
+        assert_eq!(adventure.scene, None);
+    }
+
+    #[test]
+    fn reloading_without_the_current_scene_moves_to_another() {
+        let book = BOOK
+            .replace("    scenes: [room]\n", "    scenes: [room, hall]\n")
+            .replace("scenes:\n  room:", "scenes:\n  hall:\n    name: Hall\n    background: A hall.\n    decisions: [wait]\n  room:");
+        let mut adventure = adventure(&book);
+        adventure.goto("start", "hall");
+        adventure.rebind(serde_yaml::from_str(BOOK).unwrap());
+        assert_eq!(adventure.state, AdventureState::Started);
+        assert_eq!(adventure.scene.as_deref(), Some("room"));
+    }
+
+    const CREATION: &str = "
+story:
+  title: Test
+  chapters: [start]
+  creation:
+    name: player_name
+    choices:
+      - id: class
+        question: Choose your class.
+        options:
+          - id: warrior
+            text: Warrior
+            provides: [sword]
+          - id: mage
+            text: Mage
+            provides: [arcane]
+    points:
+      total: 4
+      counters: [strength, wits]
+      max: 3
+chapters:
+  start:
+    title: Start
+    scenes: [room]
+scenes:
+  room:
+    name: Room
+    background: A room.
+    decisions: []
+items:
+  sword:
+    name: Sword
+    description: A sword.
+decisions:
+characters:
+consequences:
+statuses:
+counters:
+  strength:
+    name: Strength
+    value: 1
+    visible: true
+  wits:
+    name: Wits
+    value: 1
+    visible: true
+triggers:
+variables:
+  player_name:
+    name: Name
+";
+
+    fn character(class: &str, points: &[(&str, u32)]) -> PlayerCharacter {
+        PlayerCharacter {
+            name: Some("Ada".to_string()),
+            choices: HashMap::from([("class".to_string(), class.to_string())]),
+            points: points.iter().map(|(counter, points)| (counter.to_string(), *points)).collect(),
+        }
+    }
+
+    #[test]
+    fn creating_a_character_applies_it() {
+        let mut adventure = new_adventure(CREATION);
+        adventure.create_character(character("warrior", &[("strength", 3), ("wits", 1)])).unwrap();
+        assert_eq!(adventure.variables["player_name"], "Ada");
+        assert_eq!(adventure.item_count("sword"), 1);
+        assert_eq!(adventure.counters["strength"], 4);
+        assert_eq!(adventure.counters["wits"], 2);
+    }
+
+    #[test]
+    fn character_points_are_limited() {
+        let mut adventure = new_adventure(CREATION);
+        assert!(adventure.create_character(character("mage", &[("strength", 3), ("wits", 3)])).is_err());
+        assert!(adventure.create_character(character("mage", &[("wits", 4)])).is_err());
+        assert!(adventure.create_character(character("mage", &[("luck", 1)])).is_err());
+        assert!(adventure.create_character(character("bard", &[])).is_err());
+        assert_eq!(adventure.counters["strength"], 1);
+        assert!(adventure.character.is_none());
+    }
+
+    #[test]
+    fn huge_character_points_are_rejected() {
+        let book = CREATION.replace("total: 4", "total: 4294967295").replace("      max: 3\n", "");
+        let mut adventure = new_adventure(&book);
+        assert!(adventure.create_character(character("mage", &[("strength", u32::MAX), ("wits", 1)])).is_err());
+        assert!(adventure.create_character(character("mage", &[("strength", u32::MAX)])).is_err());
+        assert!(adventure.create_character(character("mage", &[("strength", i32::MAX as u32)])).is_err());
+        assert_eq!(adventure.counters["strength"], 1);
+    }
+
+    #[test]
+    fn characters_are_created_before_starting() {
+        let mut adventure = adventure(CREATION);
+        assert!(adventure.create_character(character("mage", &[])).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use crate::book::*;
+use crate::format::format_questbook;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// The kinds of entries that can be added with `add`.
+pub const ADDABLE: [&str; 3] = ["scene", "decision", "consequence"];
+
+/**
+ * The source of a new questbook, with one chapter, scene, decision and consequence to
+ * build on.
+ */
+pub fn skeleton(title: &str) -> Result<String, String> {
+    let title = serde_yaml::to_string(title).map_err(|e| e.to_string())?;
+    let source = format!("# Story is the overview of the adventure.
+story:
+  title: {}
+  chapters:
+    - beginning
+
+# Chapters are made of scenes, and have requirements to unlock.
+chapters:
+  beginning:
+    title: The beginning
+    scenes:
+      - start
+
+# Scenes are the places where decisions can be made in a chapter.
+scenes:
+  start:
+    name: The start
+    background: Where the adventure begins.
+    decisions:
+      - look_around
+
+# Items are things the player can collect into their inventory.
+items:
+
+# Decisions are the things the player can do in a scene.
+decisions:
+  look_around:
+    decision: Look around.
+    consequences:
+      - looked_around
+
+# Characters are the people and things the player meets.
+characters:
+
+# Consequences are the results of making a decision.
+consequences:
+  looked_around:
+    conclusion: There's nothing here yet.
+
+# Statuses are the temporary states that can afflict the player.
+statuses:
+
+# Counters are numbers that track the player's progress.
+counters:
+
+# Triggers apply consequences whenever their requirements are met.
+triggers:
+", title.trim_end());
+    format_questbook(&source)
+}
+
+/**
+ * Asks for the fields of new entries, one line at a time.
+ */
+pub struct Prompter<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, output: W) -> Prompter<R, W> {
+        Prompter { input, output }
+    }
+
+    /**
+     * Ask a question, returning the trimmed answer.
+     */
+    fn ask(&mut self, question: &str) -> Result<String, String> {
+        write!(self.output, "{}: ", question).and_then(|_| self.output.flush()).map_err(|e| e.to_string())?;
+        let mut answer = String::new();
+        match self.input.read_line(&mut answer) {
+            Ok(0) => Err(format!("No answer for: {}", question)),
+            Ok(_) => Ok(answer.trim().to_string()),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    fn tell(&mut self, message: &str) -> Result<(), String> {
+        writeln!(self.output, "{}", message).map_err(|e| e.to_string())
+    }
+
+    /**
+     * Ask until the answer isn't empty.
+     */
+    fn ask_text(&mut self, question: &str) -> Result<String, String> {
+        loop {
+            let answer = self.ask(question)?;
+            if !answer.is_empty() {
+                return Ok(answer);
+            }
+        }
+    }
+
+    /**
+     * Ask for the ID of a new entry, until it's a valid ID that isn't taken.
+     */
+    fn ask_new_id<T>(&mut self, kind: &str, entries: &HashMap<String, T>) -> Result<String, String> {
+        loop {
+            let id = self.ask_text(&format!("ID of the {}", kind))?;
+            if id.contains(char::is_whitespace) {
+                self.tell("IDs can't contain spaces")?;
+            } else if entries.contains_key(&id) {
+                self.tell(&format!("There's already a {} {}", kind, id))?;
+            } else {
+                return Ok(id);
+            }
+        }
+    }
+
+    /**
+     * Ask for the ID of an existing entry, until it exists. If `optional`, an empty answer
+     * gives `None`.
+     */
+    fn ask_ref<T>(&mut self, question: &str, kind: &str, entries: &HashMap<String, T>, optional: bool) -> Result<Option<String>, String> {
+        loop {
+            let id = self.ask(question)?;
+            if id.is_empty() && optional {
+                return Ok(None);
+            }
+            if entries.contains_key(&id) {
+                return Ok(Some(id));
+            }
+            self.tell(&format!("There's no {} {}", kind, id))?;
+        }
+    }
+
+    /**
+     * Ask for a list of IDs of existing entries, separated by commas or spaces, until all of
+     * them exist.
+     */
+    fn ask_refs<T>(&mut self, question: &str, kind: &str, entries: &HashMap<String, T>) -> Result<Vec<String>, String> {
+        loop {
+            let ids = split_ids(&self.ask(question)?);
+            match ids.iter().find(|id| !entries.contains_key(*id)) {
+                Some(missing) => self.tell(&format!("There's no {} {}", kind, missing))?,
+                None => return Ok(ids),
+            }
+        }
+    }
+
+    /**
+     * Ask for a list of IDs, separated by commas or spaces. `None` if there are none.
+     */
+    fn ask_ids(&mut self, question: &str) -> Result<Option<Vec<String>>, String> {
+        let ids = split_ids(&self.ask(question)?);
+        Ok(if ids.is_empty() { None } else { Some(ids) })
+    }
+
+    fn ask_optional(&mut self, question: &str) -> Result<Option<String>, String> {
+        let answer = self.ask(question)?;
+        Ok(if answer.is_empty() { None } else { Some(answer) })
+    }
+}
+
+fn split_ids(answer: &str) -> Vec<String> {
+    answer.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// An entry added to a questbook.
+#[derive(Debug, PartialEq)]
+pub struct Added {
+    pub id: String,
+    /// Section the entry is in.
+    pub section: &'static str,
+    /// Path to the list the entry's ID was added to, like `["chapters", "start", "scenes"]`.
+    pub list: Option<Vec<String>>,
+}
+
+/**
+ * Prompt for a new scene, decision or consequence and add it to the questbook, returning
+ * where it was added.
+ */
+pub fn add<R: BufRead, W: Write>(questbook: &mut Questbook, kind: &str, prompter: &mut Prompter<R, W>) -> Result<Added, String> {
+    match kind {
+        "scene" => add_scene(questbook, prompter),
+        "decision" => add_decision(questbook, prompter),
+        "consequence" => add_consequence(questbook, prompter),
+        _ => Err(format!("Can't add a {}, only a scene, decision or consequence", kind)),
+    }
+}
+
+fn add_scene<R: BufRead, W: Write>(questbook: &mut Questbook, prompter: &mut Prompter<R, W>) -> Result<Added, String> {
+    let id = prompter.ask_new_id("scene", &questbook.scenes)?;
+    let name = prompter.ask_text("Name")?;
+    let background = prompter.ask_text("Background")?;
+    let decisions = prompter.ask_refs("Decisions (IDs, optional)", "decision", &questbook.decisions)?;
+    let chapter = prompter.ask_ref("Chapter it's in", "chapter", &questbook.chapters, false)?.unwrap();
+
+    questbook.chapters.get_mut(&chapter).unwrap().scenes.push(id.clone());
+    questbook.scenes.insert(id.clone(), Scene {
+        name,
+        background,
+        exposition: None,
+        characters: None,
+        decisions,
+        requirements: None,
+        on_enter: None,
+        on_exit: None,
+        hints: None,
+    });
+    Ok(Added {
+        id,
+        section: "scenes",
+        list: Some(vec!["chapters".to_string(), chapter, "scenes".to_string()]),
+    })
+}
+
+fn add_decision<R: BufRead, W: Write>(questbook: &mut Questbook, prompter: &mut Prompter<R, W>) -> Result<Added, String> {
+    let id = prompter.ask_new_id("decision", &questbook.decisions)?;
+    let decision = prompter.ask_text("Decision text")?;
+    let description = prompter.ask_optional("Description (optional)")?;
+    let consequences = prompter.ask_refs("Consequences (IDs, optional)", "consequence", &questbook.consequences)?;
+    let scene = prompter.ask_ref("Scene it's made in (empty for any time)", "scene", &questbook.scenes, true)?;
+
+    let list = match scene {
+        Some(scene) => {
+            questbook.scenes.get_mut(&scene).unwrap().decisions.push(id.clone());
+            vec!["scenes".to_string(), scene, "decisions".to_string()]
+        },
+        None => {
+            questbook.story.decisions.get_or_insert_with(Vec::new).push(id.clone());
+            vec!["story".to_string(), "decisions".to_string()]
+        },
+    };
+    questbook.decisions.insert(id.clone(), Decision {
+        description,
+        decision,
+        consequences,
+        outcomes: None,
+        check: None,
+        prompt: None,
+        requirements: None,
+        show_when_locked: None,
+        locked_text: None,
+    });
+    Ok(Added {
+        id,
+        section: "decisions",
+        list: Some(list),
+    })
+}
+
+fn add_consequence<R: BufRead, W: Write>(questbook: &mut Questbook, prompter: &mut Prompter<R, W>) -> Result<Added, String> {
+    let id = prompter.ask_new_id("consequence", &questbook.consequences)?;
+    let conclusion = prompter.ask_text("Conclusion")?;
+    let scene = prompter.ask_ref("Scene to move to (optional)", "scene", &questbook.scenes, true)?;
+    let provides = prompter.ask_ids("Items, statuses or tags provided (IDs, optional)")?;
+    let costs = prompter.ask_ids("Items, statuses or tags removed (IDs, optional)")?;
+    let decision = prompter.ask_ref("Decision it's a consequence of (optional)", "decision", &questbook.decisions, true)?;
+
+    if let Some(decision) = &decision {
+        questbook.decisions.get_mut(decision).unwrap().consequences.push(id.clone());
+    }
+    questbook.consequences.insert(id.clone(), Consequence {
+        conclusion,
+        description: None,
+        scene,
+        provides,
+        costs,
+        counter: None,
+        variable: None,
+        roll: None,
+        schedule: None,
+        reset: None,
+        clear: None,
+        once: None,
+        ending: None,
+    });
+    Ok(Added {
+        id,
+        section: "consequences",
+        list: decision.map(|decision| vec!["decisions".to_string(), decision, "consequences".to_string()]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn questbook() -> Questbook {
+        serde_yaml::from_str(&skeleton("Test").unwrap()).unwrap()
+    }
+
+    /**
+     * Add an entry with the answers given, one per line, returning what was added and
+     * everything the prompter wrote.
+     */
+    fn add_with(questbook: &mut Questbook, kind: &str, answers: &str) -> (Result<Added, String>, String) {
+        let mut output = Vec::new();
+        let mut prompter = Prompter::new(answers.as_bytes(), &mut output);
+        let added = add(questbook, kind, &mut prompter);
+        (added, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn adds_a_scene_to_a_chapter() {
+        let mut questbook = questbook();
+        let (added, _) = add_with(&mut questbook, "scene", "cave\nThe cave\nIt's dark.\nlook_around\nbeginning\n");
+        assert_eq!(added, Ok(Added {
+            id: "cave".to_string(),
+            section: "scenes",
+            list: Some(vec!["chapters".to_string(), "beginning".to_string(), "scenes".to_string()]),
+        }));
+        let scene = &questbook.scenes["cave"];
+        assert_eq!((scene.name.as_str(), scene.background.as_str()), ("The cave", "It's dark."));
+        assert_eq!(scene.decisions, vec!["look_around"]);
+        assert_eq!(questbook.chapters["beginning"].scenes, vec!["start", "cave"]);
+    }
+
+    #[test]
+    fn adds_a_decision_to_a_scene_or_the_story() {
+        let mut questbook = questbook();
+        let (added, _) = add_with(&mut questbook, "decision", "shout\nShout.\n\nlooked_around\nstart\n");
+        assert_eq!(added.unwrap().list, Some(vec!["scenes".to_string(), "start".to_string(), "decisions".to_string()]));
+        let decision = &questbook.decisions["shout"];
+        assert_eq!(decision.decision, "Shout.");
+        assert_eq!(decision.description, None);
+        assert_eq!(decision.consequences, vec!["looked_around"]);
+        assert_eq!(questbook.scenes["start"].decisions, vec!["look_around", "shout"]);
+
+        let (added, _) = add_with(&mut questbook, "decision", "wait\nWait.\nTime passes.\n\n\n");
+        assert_eq!(added.unwrap().list, Some(vec!["story".to_string(), "decisions".to_string()]));
+        assert_eq!(questbook.decisions["wait"].description.as_deref(), Some("Time passes."));
+        assert_eq!(questbook.story.decisions, Some(vec!["wait".to_string()]));
+    }
+
+    #[test]
+    fn adds_a_consequence_to_a_decision() {
+        let mut questbook = questbook();
+        let (added, _) = add_with(&mut questbook, "consequence", "found_key\nYou find a key.\n\nkey, torch\n\nlook_around\n");
+        assert_eq!(added, Ok(Added {
+            id: "found_key".to_string(),
+            section: "consequences",
+            list: Some(vec!["decisions".to_string(), "look_around".to_string(), "consequences".to_string()]),
+        }));
+        let consequence = &questbook.consequences["found_key"];
+        assert_eq!(consequence.conclusion, "You find a key.");
+        assert_eq!(consequence.scene, None);
+        assert_eq!(consequence.provides, Some(vec!["key".to_string(), "torch".to_string()]));
+        assert_eq!(consequence.costs, None);
+        assert_eq!(questbook.decisions["look_around"].consequences, vec!["looked_around", "found_key"]);
+
+        let (added, _) = add_with(&mut questbook, "consequence", "left\nYou leave.\nstart\n\n\n\n");
+        assert_eq!(added.unwrap().list, None);
+        assert_eq!(questbook.consequences["left"].scene.as_deref(), Some("start"));
+    }
+
+    #[test]
+    fn asks_again_until_answers_are_valid() {
+        let mut questbook = questbook();
+        let (added, output) = add_with(&mut questbook, "scene", "start\nmy cave\ncave\n\nThe cave\nIt's dark.\nnothing\n\nnowhere\nbeginning\n");
+        assert_eq!(added.unwrap().id, "cave");
+        assert!(output.contains("There's already a scene start"));
+        assert!(output.contains("IDs can't contain spaces"));
+        assert!(output.contains("There's no decision nothing"));
+        assert!(output.contains("There's no chapter nowhere"));
+        assert!(questbook.scenes["cave"].decisions.is_empty());
+    }
+
+    #[test]
+    fn running_out_of_answers_is_an_error() {
+        let mut questbook = questbook();
+        let (added, _) = add_with(&mut questbook, "scene", "cave\nThe cave\n");
+        assert_eq!(added, Err("No answer for: Background".to_string()));
+        assert!(!questbook.scenes.contains_key("cave"));
+    }
+}
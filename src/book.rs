@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 use std::fs::File;
 use serde::{Serialize, Deserialize};
+use regex::{Regex, RegexBuilder};
+use crate::format::rewrite_questbook;
+use crate::random::Dice;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Questbook {
     pub story: Story,
     pub chapters: HashMap<String, Chapter>,
@@ -14,96 +17,380 @@ pub struct Questbook {
     pub statuses: HashMap<String, Status>,
     pub counters: HashMap<String, Counter>,
     pub triggers: HashMap<String, Trigger>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub recipes: HashMap<String, Recipe>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub endings: HashMap<String, Ending>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub achievements: HashMap<String, Achievement>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub quests: HashMap<String, Quest>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, Variable>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Story {
     /// Title of the story.
     pub title: String,
     /// IDs of the chapters in order.
     pub chapters: Vec<String>,
     /// IDs of decisions that can be made at any time.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub decisions: Option<Vec<String>>,
+    /// Seed for the random number generator, for reproducible playthroughs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Whether the player can go back to an earlier chapter when the requirements of the
+    /// chapters after it stop being met. By default, chapters only move forward.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_regression: Option<bool>,
+    /// How the player creates their character before the adventure starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creation: Option<Creation>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Creation {
+    /// ID of the variable the player's name is stored in. The name isn't asked for without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Choices the player makes in order, like a class or a background.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub choices: Option<Vec<CreationChoice>>,
+    /// Points the player distributes among counters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<CreationPoints>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CreationChoice {
+    /// ID of the choice.
+    pub id: String,
+    /// Question asked to the player.
+    pub question: String,
+    /// Options the player picks one of.
+    pub options: Vec<CreationOption>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CreationOption {
+    /// ID of the option.
+    pub id: String,
+    /// Text shown to the player.
+    pub text: String,
+    /// Description of the option.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// IDs of the items, statuses, or tags the option provides.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provides: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CreationPoints {
+    /// Number of points to distribute.
+    pub total: u32,
+    /// IDs of the counters the points can go into.
+    pub counters: Vec<String>,
+    /// Most points that can go into a single counter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Chapter {
     /// Title of the chapter.
     pub title: String,
     /// IDs of the scenes in this chapter.
     pub scenes: Vec<String>,
     /// Requirements to access this chapter.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub requirements: Option<Vec<Requirement>>,
+    /// Text shown when the player enters the chapter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intro: Option<String>,
+    /// IDs of the consequences applied when the player enters the chapter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_enter: Option<Vec<String>>,
+    /// Hints for anywhere in the chapter, from the gentlest to the most revealing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hints: Option<Vec<Hint>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Scene {
     /// Name of the scene.
     pub name: String,
     /// Background text for the scene.
     pub background: String,
     /// Exposition text for the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub exposition: Option<Vec<Exposition>>,
     /// IDs of the characters in this scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub characters: Option<Vec<String>>,
     /// IDs of the decisions that can be made in this scene.
     pub decisions: Vec<String>,
     /// Requirements to access this scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requirements: Option<Vec<Requirement>>,
+    /// IDs of the consequences applied when the player enters the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_enter: Option<Vec<String>>,
+    /// IDs of the consequences applied when the player leaves the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<Vec<String>>,
+    /// Hints for the scene, from the gentlest to the most revealing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hints: Option<Vec<Hint>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hint {
+    /// Text of the hint.
+    pub text: String,
+    /// Requirements for the hint to be given.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub requirements: Option<Vec<Requirement>>,
+    /// IDs of the consequences applied when the hint is given, as its cost.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Item {
     /// Name of the item.
     pub name: String,
     /// Description of the item.
     pub description: String,
+    /// IDs of the consequences of using the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_use: Option<Vec<String>>,
+    /// Whether using the item uses one up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consumable: Option<bool>,
+    /// Whether the player can drop the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub droppable: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    /// IDs of the items combined, each one is used up.
+    pub ingredients: Vec<String>,
+    /// IDs of the consequences of combining the items.
+    pub consequences: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Decision {
     /// Description of the decision.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// The text for making the decision.
     pub decision: String,
     /// IDs of the consequences of this decision.
+    #[serde(default)]
     pub consequences: Vec<String>,
+    /// Weighted outcomes, one of which is picked at random when the decision is made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcomes: Option<Vec<Outcome>>,
+    /// Skill check rolled when the decision is made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check: Option<Check>,
+    /// Question the player answers in their own words when the decision is made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<Prompt>,
     /// Requirements to access this decision.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub requirements: Option<Vec<Requirement>>,
+    /// Whether to show the decision to the player when its requirements aren't met.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_when_locked: Option<bool>,
+    /// Hint shown to the player when the decision is locked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked_text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    /// Relative chance of this outcome being picked.
+    pub weight: u32,
+    /// IDs of the consequences of this outcome.
+    pub consequences: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Check {
+    /// Dice to roll.
+    pub dice: Dice,
+    /// IDs of the counters added to the roll.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counters: Option<Vec<String>>,
+    /// Total the roll must reach to succeed.
+    pub difficulty: i32,
+    /// IDs of the consequences applied when the check succeeds.
+    pub success: Vec<String>,
+    /// IDs of the consequences applied when the check fails.
+    pub failure: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Prompt {
+    /// Question asked to the player.
+    pub question: String,
+    /// Accepted answers, ignoring case.
+    #[serde(default)]
+    pub answers: Vec<String>,
+    /// Regular expression an accepted answer matches in full, ignoring case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// IDs of the consequences applied when the answer is accepted.
+    pub success: Vec<String>,
+    /// IDs of the consequences applied when the answer isn't accepted.
+    pub failure: Vec<String>,
+    /// ID of the variable the answer is stored in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variable: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Requirement {
     Require(String),
+    /// Requires at least this many of an item.
+    RequireCount(String, u32),
     Refuse(String),
     CounterEqual(String, i32),
     CounterLessThan(String, i32),
     CounterGreaterThan(String, i32),
+    /// Passes with the given percent chance, re-rolled every turn.
+    Chance(u32),
+    TurnEqual(u32),
+    TurnLessThan(u32),
+    TurnGreaterThan(u32),
+    /// Requires a variable to have this value, ignoring case.
+    VariableEqual(String, String),
+    /// Requires a variable to have one of these values, ignoring case.
+    VariableIn(String, Vec<String>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Consequence {
     /// Description of the action taken.
     pub conclusion: String,
     /// Description of the consequence.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// Scene to move to.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub scene: Option<String>,
     /// IDs of the items, statuses, or tags to apply.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub provides: Option<Vec<String>>, // Items, statuses or tags to apply.
-    /// IDs of the items, or statuses to remove.
-    pub costs: Option<Vec<String>>, // Items, statuses to remove.
+    /// IDs of the items, statuses, or tags to remove.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub costs: Option<Vec<String>>, // Items, statuses or tags to remove.
     /// ID of the counters to update and amount to update it.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub counter: Option<(String, i32)>,
+    /// ID of the variable to set and the value to set it to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variable: Option<(String, String)>,
+    /// ID of the counter to update and dice to roll for the amount.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<(String, Dice)>,
+    /// IDs of consequences to apply later, and in how many turns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Vec<(String, u32)>>,
+    /// IDs of the counters to reset to their initial value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset: Option<Vec<String>>,
+    /// Categories of state to clear entirely.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear: Option<Vec<StateCategory>>,
+    /// Whether the consequence only applies the first time, and is skipped after that.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub once: Option<bool>,
+    /// ID of the ending the adventure reaches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ending: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateCategory {
+    /// Remove all items.
+    Inventory,
+    /// Remove all statuses.
+    Statuses,
+    /// Remove all tags.
+    Tags,
+    /// Reset all counters to their initial value.
+    Counters,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ending {
+    /// Title of the ending.
+    pub title: String,
+    /// Text shown when the ending is reached.
+    pub epilogue: String,
+    /// Score for reaching the ending, to rank it against the others.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i32>,
+    /// Whether it's a good or bad ending.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EndingKind>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndingKind {
+    Good,
+    Bad,
+    Neutral,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Achievement {
+    /// Title of the achievement.
+    pub title: String,
+    /// What the player has to do to earn it.
+    pub description: String,
+    /// Requirements to earn the achievement.
+    pub requirements: Vec<Requirement>,
+    /// IDs of tags given at the start of every new game once the achievement is earned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unlocks: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quest {
+    /// Title of the quest.
+    pub title: String,
+    /// What the player is working toward.
+    pub description: String,
+    /// Requirements for the quest to start. Quests without any start straight away.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<Vec<Requirement>>,
+    /// Requirements to complete the quest.
+    pub complete: Vec<Requirement>,
+    /// Requirements that fail the quest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fail: Option<Vec<Requirement>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Status {
     /// Description of the status.
-    description: String,
+    pub description: String,
+    /// Number of turns the status lasts before it is removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Character {
     pub name: String,
     pub description: String,
@@ -111,41 +398,59 @@ pub struct Character {
     pub states: HashMap<String, CharacterState>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct CharacterState {
     pub description: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Exposition {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub requirements: Option<Vec<Requirement>>,
     pub text: Vec<Text>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Text {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub speaker: Option<String>,
     pub text: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Counter {
     pub name: String,
     pub value: i32,
     pub visible: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Variable {
+    /// Name of the variable.
+    pub name: String,
+    /// Initial value of the variable.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub value: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Trigger {
     pub description: String,
     pub requirements: Vec<Requirement>,
     pub consequences: Vec<String>,
 }
 
-pub fn load_questbook(bookfile: &str) -> Questbook {
-    let file = File::open(bookfile).unwrap();
-    let questbook: Questbook = serde_yaml::from_reader(file).unwrap();
-    questbook
+/**
+ * Load a questbook from a YAML file, or a JSON file if it has a `.json` extension.
+ */
+pub fn load_questbook(bookfile: &str) -> Result<Questbook, String> {
+    let file = File::open(bookfile).map_err(|e| format!("Can't open {}: {}", bookfile, e))?;
+    let questbook: Questbook = if bookfile.ends_with(".json") {
+        serde_json::from_reader(file).map_err(|e| format!("Can't read {}: {}", bookfile, e))?
+    } else {
+        serde_yaml::from_reader(file).map_err(|e| format!("Can't read {}: {}", bookfile, e))?
+    };
+    Ok(questbook)
 }
 
 impl Questbook {
@@ -153,6 +458,13 @@ impl Questbook {
         &self.story.title
     }
 
+    /**
+     * Write the questbook as YAML, in the canonical layout.
+     */
+    pub fn to_yaml(&self) -> Result<String, String> {
+        rewrite_questbook("", self)
+    }
+
     pub fn get_consequences_from_decision(&self, decision: &str) -> Vec<String> {
         let mut consequences = Vec::new();
         if let Some(decision) = self.decisions.get(decision) {
@@ -165,6 +477,25 @@ impl Questbook {
 
 }
 
+impl Prompt {
+    /**
+     * Regular expression for the pattern, matching whole answers and ignoring case.
+     */
+    pub fn regex(&self) -> Option<Result<Regex, regex::Error>> {
+        let pattern = self.pattern.as_ref()?;
+        Some(RegexBuilder::new(&format!("^(?:{})$", pattern)).case_insensitive(true).build())
+    }
+
+    /**
+     * Whether an answer is one of the accepted answers, or matches the pattern.
+     */
+    pub fn accepts(&self, answer: &str) -> bool {
+        let answer = answer.trim();
+        self.answers.iter().any(|accepted| accepted.trim().to_lowercase() == answer.to_lowercase())
+            || matches!(self.regex(), Some(Ok(regex)) if regex.is_match(answer))
+    }
+}
+
 pub trait Describe {
     fn describe(&self) -> Vec<String>;
 }
@@ -0,0 +1,496 @@
+use crate::book::*;
+use crate::validator::{validate, Issue};
+
+/// The entry most recently added to a builder, which requirements and other details apply to.
+#[derive(Debug)]
+enum Current {
+    None,
+    Chapter(String),
+    Scene(String),
+    Decision(String),
+    Consequence(String),
+    Item(String),
+    Trigger(String),
+}
+
+/**
+ * Builds a questbook in code. Scenes are added to the last chapter added, and decisions to
+ * the last scene added. Details such as requirements apply to the last entry added.
+ *
+ * ```rust
+ * use questbook::builder::QuestbookBuilder;
+ *
+ * let questbook = QuestbookBuilder::new("The Swamp")
+ *     .chapter("entrance", "Chapter 1: Entrance")
+ *     .scene("swamp", "Forbidden Swamp", "You reach the edge of the swamp.")
+ *     .decision("take_sword", "Take the sword.", &["took_sword"])
+ *     .refuses("rusty_sword")
+ *     .consequence("took_sword", "You pull the sword out of the mud.")
+ *     .provides("rusty_sword")
+ *     .item("rusty_sword", "Rusty sword", "An old sword, still sharp.")
+ *     .build()
+ *     .unwrap();
+ * assert_eq!(questbook.story.chapters, vec!["entrance"]);
+ * assert!(questbook.to_yaml().unwrap().contains("rusty_sword:"));
+ * ```
+ */
+#[derive(Debug)]
+pub struct QuestbookBuilder {
+    questbook: Questbook,
+    current: Current,
+    last_chapter: Option<String>,
+    last_scene: Option<String>,
+    /// Mistakes in the use of the builder, reported by `build()`.
+    errors: Vec<String>,
+}
+
+impl QuestbookBuilder {
+    pub fn new(title: &str) -> QuestbookBuilder {
+        let mut questbook = Questbook::default();
+        questbook.story.title = title.to_string();
+        QuestbookBuilder {
+            questbook,
+            current: Current::None,
+            last_chapter: None,
+            last_scene: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /**
+     * Seed the random number generator for every playthrough.
+     */
+    pub fn seed(mut self, seed: u64) -> QuestbookBuilder {
+        self.questbook.story.seed = Some(seed);
+        self
+    }
+
+    /**
+     * Add a chapter after the chapters already added.
+     */
+    pub fn chapter(mut self, id: &str, title: &str) -> QuestbookBuilder {
+        if self.questbook.chapters.contains_key(id) {
+            self.errors.push(format!("Chapter {} is added twice", id));
+        }
+        self.questbook.story.chapters.push(id.to_string());
+        self.questbook.chapters.insert(id.to_string(), Chapter {
+            title: title.to_string(),
+            scenes: Vec::new(),
+            requirements: None,
+            intro: None,
+            on_enter: None,
+            hints: None,
+        });
+        self.last_chapter = Some(id.to_string());
+        self.current = Current::Chapter(id.to_string());
+        self
+    }
+
+    /**
+     * Add a scene to the last chapter added.
+     */
+    pub fn scene(mut self, id: &str, name: &str, background: &str) -> QuestbookBuilder {
+        if self.questbook.scenes.contains_key(id) {
+            self.errors.push(format!("Scene {} is added twice", id));
+        }
+        match self.last_chapter.as_ref().and_then(|c| self.questbook.chapters.get_mut(c)) {
+            Some(chapter) => chapter.scenes.push(id.to_string()),
+            None => self.errors.push(format!("Scene {} is added before any chapter", id)),
+        }
+        self.questbook.scenes.insert(id.to_string(), Scene {
+            name: name.to_string(),
+            background: background.to_string(),
+            exposition: None,
+            characters: None,
+            decisions: Vec::new(),
+            requirements: None,
+            on_enter: None,
+            on_exit: None,
+            hints: None,
+        });
+        self.last_scene = Some(id.to_string());
+        self.current = Current::Scene(id.to_string());
+        self
+    }
+
+    /**
+     * Add a decision with its consequences to the last scene added.
+     */
+    pub fn decision(mut self, id: &str, text: &str, consequences: &[&str]) -> QuestbookBuilder {
+        match self.last_scene.as_ref().and_then(|s| self.questbook.scenes.get_mut(s)) {
+            Some(scene) => scene.decisions.push(id.to_string()),
+            None => self.errors.push(format!("Decision {} is added before any scene", id)),
+        }
+        self.insert_decision(id, text, consequences)
+    }
+
+    /**
+     * Add a decision with its consequences that can be made at any time.
+     */
+    pub fn story_decision(mut self, id: &str, text: &str, consequences: &[&str]) -> QuestbookBuilder {
+        self.questbook.story.decisions.get_or_insert_with(Vec::new).push(id.to_string());
+        self.insert_decision(id, text, consequences)
+    }
+
+    fn insert_decision(mut self, id: &str, text: &str, consequences: &[&str]) -> QuestbookBuilder {
+        if self.questbook.decisions.contains_key(id) {
+            self.errors.push(format!("Decision {} is added twice", id));
+        }
+        self.questbook.decisions.insert(id.to_string(), Decision {
+            description: None,
+            decision: text.to_string(),
+            consequences: ids(consequences),
+            outcomes: None,
+            check: None,
+            prompt: None,
+            requirements: None,
+            show_when_locked: None,
+            locked_text: None,
+        });
+        self.current = Current::Decision(id.to_string());
+        self
+    }
+
+    pub fn consequence(mut self, id: &str, conclusion: &str) -> QuestbookBuilder {
+        if self.questbook.consequences.contains_key(id) {
+            self.errors.push(format!("Consequence {} is added twice", id));
+        }
+        self.questbook.consequences.insert(id.to_string(), Consequence {
+            conclusion: conclusion.to_string(),
+            description: None,
+            scene: None,
+            provides: None,
+            costs: None,
+            counter: None,
+            variable: None,
+            roll: None,
+            schedule: None,
+            reset: None,
+            clear: None,
+            once: None,
+            ending: None,
+        });
+        self.current = Current::Consequence(id.to_string());
+        self
+    }
+
+    pub fn item(mut self, id: &str, name: &str, description: &str) -> QuestbookBuilder {
+        if self.questbook.items.contains_key(id) {
+            self.errors.push(format!("Item {} is added twice", id));
+        }
+        self.questbook.items.insert(id.to_string(), Item {
+            name: name.to_string(),
+            description: description.to_string(),
+            on_use: None,
+            consumable: None,
+            droppable: None,
+        });
+        self.current = Current::Item(id.to_string());
+        self
+    }
+
+    pub fn status(mut self, id: &str, description: &str, duration: Option<u32>) -> QuestbookBuilder {
+        if self.questbook.statuses.contains_key(id) {
+            self.errors.push(format!("Status {} is added twice", id));
+        }
+        self.questbook.statuses.insert(id.to_string(), Status {
+            description: description.to_string(),
+            duration,
+        });
+        self
+    }
+
+    pub fn counter(mut self, id: &str, name: &str, value: i32, visible: bool) -> QuestbookBuilder {
+        if self.questbook.counters.contains_key(id) {
+            self.errors.push(format!("Counter {} is added twice", id));
+        }
+        self.questbook.counters.insert(id.to_string(), Counter {
+            name: name.to_string(),
+            value,
+            visible,
+        });
+        self
+    }
+
+    pub fn variable(mut self, id: &str, name: &str, value: &str) -> QuestbookBuilder {
+        if self.questbook.variables.contains_key(id) {
+            self.errors.push(format!("Variable {} is added twice", id));
+        }
+        self.questbook.variables.insert(id.to_string(), Variable {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    /**
+     * Add a trigger, whose consequences apply whenever its requirements are met.
+     */
+    pub fn trigger(mut self, id: &str, description: &str, consequences: &[&str]) -> QuestbookBuilder {
+        if self.questbook.triggers.contains_key(id) {
+            self.errors.push(format!("Trigger {} is added twice", id));
+        }
+        self.questbook.triggers.insert(id.to_string(), Trigger {
+            description: description.to_string(),
+            requirements: Vec::new(),
+            consequences: ids(consequences),
+        });
+        self.current = Current::Trigger(id.to_string());
+        self
+    }
+
+    pub fn ending(mut self, id: &str, title: &str, epilogue: &str) -> QuestbookBuilder {
+        if self.questbook.endings.contains_key(id) {
+            self.errors.push(format!("Ending {} is added twice", id));
+        }
+        self.questbook.endings.insert(id.to_string(), Ending {
+            title: title.to_string(),
+            epilogue: epilogue.to_string(),
+            score: None,
+            kind: None,
+        });
+        self
+    }
+
+    pub fn recipe(mut self, id: &str, ingredients: &[&str], consequences: &[&str]) -> QuestbookBuilder {
+        if self.questbook.recipes.contains_key(id) {
+            self.errors.push(format!("Recipe {} is added twice", id));
+        }
+        self.questbook.recipes.insert(id.to_string(), Recipe {
+            ingredients: ids(ingredients),
+            consequences: ids(consequences),
+        });
+        self
+    }
+
+    /**
+     * Add a requirement to the last chapter, scene, decision or trigger added.
+     */
+    pub fn requirement(mut self, requirement: Requirement) -> QuestbookBuilder {
+        let questbook = &mut self.questbook;
+        let requirements = match &self.current {
+            Current::Chapter(id) => questbook.chapters.get_mut(id).map(|c| c.requirements.get_or_insert_with(Vec::new)),
+            Current::Scene(id) => questbook.scenes.get_mut(id).map(|s| s.requirements.get_or_insert_with(Vec::new)),
+            Current::Decision(id) => questbook.decisions.get_mut(id).map(|d| d.requirements.get_or_insert_with(Vec::new)),
+            Current::Trigger(id) => questbook.triggers.get_mut(id).map(|t| &mut t.requirements),
+            _ => None,
+        };
+        match requirements {
+            Some(requirements) => requirements.push(requirement),
+            None => self.errors.push(format!("Requirement {:?} isn't for a chapter, scene, decision or trigger", requirement)),
+        }
+        self
+    }
+
+    /**
+     * Require an item, status or tag.
+     */
+    pub fn requires(self, id: &str) -> QuestbookBuilder {
+        self.requirement(Requirement::Require(id.to_string()))
+    }
+
+    /**
+     * Require not having an item, status or tag.
+     */
+    pub fn refuses(self, id: &str) -> QuestbookBuilder {
+        self.requirement(Requirement::Refuse(id.to_string()))
+    }
+
+    pub fn counter_at_least(mut self, counter: &str, value: i32) -> QuestbookBuilder {
+        if let Some(below) = value.checked_sub(1) {
+            return self.requirement(Requirement::CounterGreaterThan(counter.to_string(), below));
+        }
+        // Every counter is at least the smallest value, so there's nothing to require.
+        if !matches!(self.current, Current::Chapter(_) | Current::Scene(_) | Current::Decision(_) | Current::Trigger(_)) {
+            self.errors.push("counter_at_least isn't for a chapter, scene, decision or trigger".to_string());
+        }
+        self
+    }
+
+    pub fn counter_below(self, counter: &str, value: i32) -> QuestbookBuilder {
+        self.requirement(Requirement::CounterLessThan(counter.to_string(), value))
+    }
+
+    /**
+     * Make the last consequence added provide an item, status or tag.
+     */
+    pub fn provides(self, id: &str) -> QuestbookBuilder {
+        self.with_consequence("provides", |c| c.provides.get_or_insert_with(Vec::new).push(id.to_string()))
+    }
+
+    /**
+     * Make the last consequence added remove an item, status or tag.
+     */
+    pub fn costs(self, id: &str) -> QuestbookBuilder {
+        self.with_consequence("costs", |c| c.costs.get_or_insert_with(Vec::new).push(id.to_string()))
+    }
+
+    /**
+     * Make the last consequence added move the player to a scene.
+     */
+    pub fn moves_to(self, scene: &str) -> QuestbookBuilder {
+        self.with_consequence("moves_to", |c| c.scene = Some(scene.to_string()))
+    }
+
+    /**
+     * Make the last consequence added change a counter.
+     */
+    pub fn changes_counter(self, counter: &str, amount: i32) -> QuestbookBuilder {
+        self.with_consequence("changes_counter", |c| c.counter = Some((counter.to_string(), amount)))
+    }
+
+    /**
+     * Make the last consequence added set a variable.
+     */
+    pub fn sets_variable(self, variable: &str, value: &str) -> QuestbookBuilder {
+        self.with_consequence("sets_variable", |c| c.variable = Some((variable.to_string(), value.to_string())))
+    }
+
+    /**
+     * Make the last consequence added end the adventure with an ending.
+     */
+    pub fn reaches_ending(self, ending: &str) -> QuestbookBuilder {
+        self.with_consequence("reaches_ending", |c| c.ending = Some(ending.to_string()))
+    }
+
+    /**
+     * Set the description of the last decision, consequence or item added.
+     */
+    pub fn description(mut self, description: &str) -> QuestbookBuilder {
+        let description = description.to_string();
+        let questbook = &mut self.questbook;
+        let found = match &self.current {
+            Current::Decision(id) => questbook.decisions.get_mut(id).map(|d| d.description = Some(description)),
+            Current::Consequence(id) => questbook.consequences.get_mut(id).map(|c| c.description = Some(description)),
+            Current::Item(id) => questbook.items.get_mut(id).map(|i| i.description = description),
+            _ => None,
+        };
+        if found.is_none() {
+            self.errors.push("description isn't for a decision, consequence or item".to_string());
+        }
+        self
+    }
+
+    fn with_consequence(mut self, what: &str, change: impl FnOnce(&mut Consequence)) -> QuestbookBuilder {
+        let consequence = match &self.current {
+            Current::Consequence(id) => self.questbook.consequences.get_mut(id),
+            _ => None,
+        };
+        match consequence {
+            Some(consequence) => change(consequence),
+            None => self.errors.push(format!("{} isn't for a consequence", what)),
+        }
+        self
+    }
+
+    /**
+     * Finish the questbook, checking that everything it refers to exists. Returns the
+     * errors found otherwise.
+     */
+    pub fn build(self) -> Result<Questbook, Vec<Issue>> {
+        let mut errors: Vec<Issue> = self.errors.into_iter().map(Issue::Error).collect();
+        errors.extend(validate(&self.questbook).into_iter().filter(|issue| issue.is_error()));
+        if errors.is_empty() {
+            Ok(self.questbook)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /**
+     * Finish the questbook and write it as YAML.
+     */
+    pub fn to_yaml(self) -> Result<String, Vec<Issue>> {
+        self.build()?.to_yaml().map_err(|error| vec![Issue::Error(error)])
+    }
+}
+
+fn ids(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swamp() -> QuestbookBuilder {
+        QuestbookBuilder::new("The Swamp")
+            .chapter("entrance", "Entrance")
+            .scene("swamp", "Swamp", "You reach the swamp.")
+            .decision("wade", "Wade in.", &["waded"])
+            .consequence("waded", "You wade in.")
+            .counter("hp", "Health", 10, true)
+    }
+
+    fn errors(builder: QuestbookBuilder) -> Vec<Issue> {
+        builder.build().unwrap_err()
+    }
+
+    #[test]
+    fn builds_chapters_scenes_and_decisions() {
+        let questbook = swamp()
+            .decision("rest", "Rest.", &["rested"])
+            .requires("tired")
+            .counter_below("hp", 5)
+            .consequence("rested", "You rest.")
+            .provides("rested")
+            .changes_counter("hp", 3)
+            .build()
+            .unwrap();
+        assert_eq!(questbook.story.chapters, vec!["entrance"]);
+        assert_eq!(questbook.chapters["entrance"].scenes, vec!["swamp"]);
+        assert_eq!(questbook.scenes["swamp"].decisions, vec!["wade", "rest"]);
+        assert_eq!(questbook.decisions["rest"].requirements, Some(vec![
+            Requirement::Require("tired".to_string()),
+            Requirement::CounterLessThan("hp".to_string(), 5),
+        ]));
+        assert_eq!(questbook.consequences["rested"].provides, Some(vec!["rested".to_string()]));
+        assert_eq!(questbook.consequences["rested"].counter, Some(("hp".to_string(), 3)));
+    }
+
+    #[test]
+    fn counter_at_least_includes_the_value() {
+        let questbook = swamp().decision("fight", "Fight.", &["waded"]).counter_at_least("hp", 5).build().unwrap();
+        assert_eq!(questbook.decisions["fight"].requirements, Some(vec![Requirement::CounterGreaterThan("hp".to_string(), 4)]));
+    }
+
+    #[test]
+    fn counter_at_least_the_smallest_value_always_passes() {
+        let questbook = swamp().decision("fight", "Fight.", &["waded"]).counter_at_least("hp", i32::MIN).build().unwrap();
+        assert_eq!(questbook.decisions["fight"].requirements, None);
+        let mut adventure = crate::adventure::Adventure::with_seed(questbook, 1);
+        adventure.start();
+        adventure.counters.insert("hp".to_string(), i32::MIN);
+        assert!(adventure.get_decisions().contains("fight"));
+    }
+
+    #[test]
+    fn details_for_the_wrong_kind_of_entry_are_errors() {
+        assert_eq!(errors(swamp().counter_at_least("hp", i32::MIN)), vec![
+            Issue::Error("counter_at_least isn't for a chapter, scene, decision or trigger".to_string()),
+        ]);
+        assert_eq!(errors(swamp().chapter("cave", "Cave").provides("sword")), vec![
+            Issue::Error("provides isn't for a consequence".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn ids_added_twice_are_errors() {
+        let builder = swamp()
+            .chapter("entrance", "Entrance again")
+            .scene("swamp", "Swamp", "Again.")
+            .decision("wade", "Wade in again.", &["waded"])
+            .consequence("waded", "Again.")
+            .counter("hp", "Health", 1, true)
+            .item("sword", "Sword", "A sword.")
+            .item("sword", "Sword", "A sword.");
+        assert_eq!(errors(builder), vec![
+            Issue::Error("Chapter entrance is added twice".to_string()),
+            Issue::Error("Scene swamp is added twice".to_string()),
+            Issue::Error("Decision wade is added twice".to_string()),
+            Issue::Error("Consequence waded is added twice".to_string()),
+            Issue::Error("Counter hp is added twice".to_string()),
+            Issue::Error("Item sword is added twice".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,434 @@
+use crate::adventure::Adventure;
+use crate::authoring::{add, skeleton, Prompter, ADDABLE};
+use crate::book::{load_questbook, Questbook};
+use crate::diff::diff;
+use crate::format::{format_questbook, insert_entry};
+use crate::graph::to_dot;
+use crate::playtest::run_script;
+use crate::stats::stats;
+use crate::textrunner::TextRunner;
+use crate::validator::validate;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const EXIT_OK: i32 = 0;
+/// The questbook couldn't be read, or has errors.
+pub const EXIT_INVALID_BOOK: i32 = 1;
+/// Something went wrong while running the command.
+pub const EXIT_RUNTIME_ERROR: i32 = 2;
+/// A playtest script failed.
+pub const EXIT_TEST_FAILED: i32 = 3;
+/// The command line couldn't be understood.
+pub const EXIT_USAGE: i32 = 64;
+
+const USAGE: &str = "Usage: questbook [command] [options] <book>
+
+Commands:
+  play <book>              Play a questbook (the default)
+  check <book>             Check a questbook for errors
+  graph <book>             Print the scenes as a Graphviz DOT graph
+  stats <book>             Print statistics about a questbook
+  test <book> <script>     Play through a questbook with a playtest script
+  convert <book> <output>  Convert a questbook to YAML or JSON, by the output extension
+  fmt <book>               Rewrite a questbook file in the canonical layout
+  diff <old> <new>         Compare two versions of a questbook
+  new <name>               Start a new questbook in <name>.questbook.yml
+  add <kind> <book>        Add a scene, decision or consequence to a questbook, asking for each field
+
+Options:
+  --seed <n>               Seed the random number generator
+  --save-dir <dir>         Directory for save files (default: saves)
+  --dev                    Developer mode
+  --transcript <file>      Write a transcript of the game to a file
+  --json                   Print stats as JSON
+  --check                  With fmt, only check the layout, and fail if it would change
+  -h, --help               Show this help
+  -V, --version            Show the version
+
+Exit codes:
+  0   Success
+  1   The questbook is invalid
+  2   Runtime error
+  3   The playtest failed
+  64  Invalid command line";
+
+/// A parsed command line.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub command: String,
+    pub args: Vec<String>,
+    pub seed: Option<u64>,
+    pub save_dir: Option<String>,
+    pub dev: bool,
+    pub transcript: Option<String>,
+    pub json: bool,
+    pub check: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+const COMMANDS: [&str; 10] = ["play", "check", "graph", "stats", "test", "convert", "fmt", "diff", "new", "add"];
+
+/**
+ * Parse command line arguments, not including the program name.
+ */
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut iter = args.iter();
+    let mut positional = Vec::new();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-V" | "--version" => options.version = true,
+            "--dev" => options.dev = true,
+            "--json" => options.json = true,
+            "--check" => options.check = true,
+            "--seed" => {
+                let seed = iter.next().ok_or("--seed needs a value")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("Invalid seed: {}", seed))?);
+            },
+            "--save-dir" => options.save_dir = Some(iter.next().ok_or("--save-dir needs a directory")?.clone()),
+            "--transcript" => options.transcript = Some(iter.next().ok_or("--transcript needs a file")?.clone()),
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if positional.is_empty() {
+        return Ok(options);
+    }
+    if COMMANDS.contains(&positional[0].as_str()) {
+        options.command = positional.remove(0);
+    } else {
+        // `questbook <book> [dev]`
+        options.command = "play".to_string();
+        if positional.len() == 2 && positional[1] == "dev" {
+            positional.pop();
+            options.dev = true;
+        }
+    }
+    options.args = positional;
+    Ok(options)
+}
+
+/**
+ * Run the command line, returning the exit code.
+ */
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return EXIT_USAGE;
+        },
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return EXIT_OK;
+    }
+    if options.version {
+        println!("questbook {}", env!("CARGO_PKG_VERSION"));
+        return EXIT_OK;
+    }
+
+    let expected_args = match options.command.as_str() {
+        "test" | "convert" | "diff" | "add" => 2,
+        "" => 0,
+        _ => 1,
+    };
+    if expected_args == 0 || options.args.len() != expected_args {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    }
+
+    let bookfile = &options.args[0];
+    match options.command.as_str() {
+        "fmt" => return fmt(&options, bookfile),
+        "new" => return new_book(bookfile),
+        "add" => return add_entry(&options.args[0], &options.args[1]),
+        _ => {},
+    }
+    let questbook = match load_questbook(bookfile) {
+        Ok(questbook) => questbook,
+        Err(error) => {
+            eprintln!("{}", error);
+            return EXIT_INVALID_BOOK;
+        },
+    };
+
+    match options.command.as_str() {
+        "play" => play(&options, questbook),
+        "check" => check(&questbook),
+        "graph" => {
+            print!("{}", to_dot(&questbook));
+            EXIT_OK
+        },
+        "stats" => {
+            let stats = stats(&questbook);
+            if options.json {
+                println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+            } else {
+                print!("{}", stats);
+            }
+            EXIT_OK
+        },
+        "test" => test(&options, questbook),
+        "convert" => convert(&questbook, &options.args[1]),
+        "diff" => match load_questbook(&options.args[1]) {
+            Ok(new) => {
+                print!("{}", diff(&questbook, &new));
+                EXIT_OK
+            },
+            Err(error) => {
+                eprintln!("{}", error);
+                EXIT_INVALID_BOOK
+            },
+        },
+        _ => unreachable!(),
+    }
+}
+
+/**
+ * Print validation issues, returning true if there were no errors. Warnings are only
+ * printed if `warnings` is set.
+ */
+fn report_issues(questbook: &Questbook, warnings: bool) -> bool {
+    let issues = validate(questbook);
+    for issue in issues.iter().filter(|i| warnings || i.is_error()) {
+        eprintln!("{}", issue);
+    }
+    !issues.iter().any(|i| i.is_error())
+}
+
+fn new_adventure(options: &Options, questbook: Questbook) -> Adventure {
+    match options.seed {
+        Some(seed) => Adventure::with_seed(questbook, seed),
+        None => Adventure::new(questbook),
+    }
+}
+
+fn play(options: &Options, questbook: Questbook) -> i32 {
+    // Only authors need to see warnings.
+    if !report_issues(&questbook, options.dev) {
+        return EXIT_INVALID_BOOK;
+    }
+    let adventure = new_adventure(options, questbook);
+    let mut textrunner = TextRunner::new(adventure, options.dev);
+    if options.dev {
+        textrunner.watch(&options.args[0]);
+    }
+    if let Some(save_dir) = &options.save_dir {
+        textrunner.save_dir(save_dir);
+    }
+    if let Some(transcript) = &options.transcript {
+        if let Err(error) = textrunner.transcript(transcript) {
+            eprintln!("Can't write transcript {}: {}", transcript, error);
+            return EXIT_RUNTIME_ERROR;
+        }
+    }
+    if let Err(error) = textrunner.start() {
+        eprintln!("{}", error);
+        return EXIT_RUNTIME_ERROR;
+    }
+    EXIT_OK
+}
+
+fn check(questbook: &Questbook) -> i32 {
+    if report_issues(questbook, true) {
+        println!("{} is valid", questbook.title());
+        EXIT_OK
+    } else {
+        EXIT_INVALID_BOOK
+    }
+}
+
+fn test(options: &Options, questbook: Questbook) -> i32 {
+    if !report_issues(&questbook, true) {
+        return EXIT_INVALID_BOOK;
+    }
+    let script = match fs::read_to_string(&options.args[1]) {
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("Can't read {}: {}", options.args[1], error);
+            return EXIT_RUNTIME_ERROR;
+        },
+    };
+    let mut adventure = new_adventure(options, questbook);
+    let failures = run_script(&mut adventure, &script);
+    if failures.is_empty() {
+        println!("Playtest passed");
+        return EXIT_OK;
+    }
+    for failure in failures {
+        println!("{}", failure);
+    }
+    EXIT_TEST_FAILED
+}
+
+fn fmt(options: &Options, bookfile: &str) -> i32 {
+    let source = match fs::read_to_string(bookfile) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Can't read {}: {}", bookfile, error);
+            return EXIT_INVALID_BOOK;
+        },
+    };
+    let formatted = match format_questbook(&source) {
+        Ok(formatted) => formatted,
+        Err(error) => {
+            eprintln!("Can't format {}: {}", bookfile, error);
+            return EXIT_INVALID_BOOK;
+        },
+    };
+    if formatted == source {
+        return EXIT_OK;
+    }
+    if options.check {
+        println!("{} isn't formatted", bookfile);
+        return EXIT_INVALID_BOOK;
+    }
+    match fs::write(bookfile, formatted) {
+        Ok(()) => EXIT_OK,
+        Err(error) => {
+            eprintln!("Can't write {}: {}", bookfile, error);
+            EXIT_RUNTIME_ERROR
+        },
+    }
+}
+
+fn new_book(name: &str) -> i32 {
+    let bookfile = format!("{}.questbook.yml", name);
+    if fs::metadata(&bookfile).is_ok() {
+        eprintln!("{} already exists", bookfile);
+        return EXIT_RUNTIME_ERROR;
+    }
+    let title = Path::new(name).file_name().and_then(|n| n.to_str()).unwrap_or(name).replace(['_', '-'], " ");
+    let mut chars = title.chars();
+    let title: String = chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default();
+    let written = skeleton(&title).and_then(|source| fs::write(&bookfile, source).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => {
+            println!("Created {}", bookfile);
+            EXIT_OK
+        },
+        Err(error) => {
+            eprintln!("Can't write {}: {}", bookfile, error);
+            EXIT_RUNTIME_ERROR
+        },
+    }
+}
+
+fn add_entry(kind: &str, bookfile: &str) -> i32 {
+    if !ADDABLE.contains(&kind) {
+        eprintln!("Can't add a {}, only a {}", kind, ADDABLE.join(", "));
+        return EXIT_USAGE;
+    }
+    let source = match fs::read_to_string(bookfile) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Can't read {}: {}", bookfile, error);
+            return EXIT_INVALID_BOOK;
+        },
+    };
+    let mut questbook: Questbook = match serde_yaml::from_str(&source) {
+        Ok(questbook) => questbook,
+        Err(error) => {
+            eprintln!("Can't read {}: {}", bookfile, error);
+            return EXIT_INVALID_BOOK;
+        },
+    };
+    let stdin = io::stdin();
+    let mut prompter = Prompter::new(stdin.lock(), io::stdout());
+    let added = match add(&mut questbook, kind, &mut prompter) {
+        Ok(added) => added,
+        Err(error) => {
+            eprintln!("{}", error);
+            return EXIT_RUNTIME_ERROR;
+        },
+    };
+    let written = insert_entry(&source, &questbook, added.section, &added.id, added.list.as_deref())
+        .and_then(|text| fs::write(bookfile, text).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => {
+            println!("Added {} {} to {}", kind, added.id, bookfile);
+            EXIT_OK
+        },
+        Err(error) => {
+            eprintln!("Can't write {}: {}", bookfile, error);
+            EXIT_RUNTIME_ERROR
+        },
+    }
+}
+
+fn convert(questbook: &Questbook, output: &str) -> i32 {
+    let converted = if output.ends_with(".json") {
+        serde_json::to_string_pretty(questbook).map_err(|e| e.to_string())
+    } else if output.ends_with(".yml") || output.ends_with(".yaml") {
+        serde_yaml::to_string(questbook).map_err(|e| e.to_string())
+    } else {
+        eprintln!("Can't tell the format of {}, use a .yml, .yaml or .json extension", output);
+        return EXIT_USAGE;
+    };
+    let written = converted.and_then(|text| fs::write(output, text).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => EXIT_OK,
+        Err(error) => {
+            eprintln!("Can't write {}: {}", output, error);
+            EXIT_RUNTIME_ERROR
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "questbooks/minimal.questbook.yml";
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    /**
+     * Write a file to a fresh temporary directory, returning its path.
+     */
+    fn temp_file(name: &str, contents: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("questbook-cli-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_options_and_the_default_command() {
+        let options = parse_args(&args(&["--seed", "7", "book.yml", "dev"])).unwrap();
+        assert_eq!(options.command, "play");
+        assert_eq!(options.args, vec!["book.yml"]);
+        assert_eq!(options.seed, Some(7));
+        assert!(options.dev);
+        assert_eq!(parse_args(&args(&["--seed", "x"])).unwrap_err(), "Invalid seed: x");
+        assert_eq!(run(&args(&["test", BOOK])), EXIT_USAGE);
+    }
+
+    #[test]
+    fn playtests_exit_with_their_result() {
+        let passing = temp_file("passing.txt", "expect ended\n");
+        let failing = temp_file("failing.txt", "expect turn 5\n");
+        assert_eq!(run(&args(&["test", BOOK, &passing])), EXIT_OK);
+        assert_eq!(run(&args(&["test", BOOK, &failing])), EXIT_TEST_FAILED);
+        assert_eq!(run(&args(&["test", BOOK, "no/such/script.txt"])), EXIT_RUNTIME_ERROR);
+    }
+
+    #[test]
+    fn games_that_cant_write_their_transcript_are_runtime_errors() {
+        let saves = temp_file("saves", "");
+        fs::remove_file(&saves).unwrap();
+        assert_eq!(run(&args(&["play", BOOK, "--save-dir", &saves, "--transcript", "no/such/dir/transcript.txt"])), EXIT_RUNTIME_ERROR);
+        // The transcript opens, but writing to it fails once the game is under way.
+        if Path::new("/dev/full").exists() {
+            assert_eq!(run(&args(&["play", BOOK, "--save-dir", &saves, "--transcript", "/dev/full"])), EXIT_RUNTIME_ERROR);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use crate::book::*;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Differences between two versions of a questbook.
+#[derive(Debug, Default)]
+pub struct BookDiff {
+    /// Changes to the story, one per field.
+    pub story: Vec<String>,
+    pub sections: Vec<SectionDiff>,
+    /// Changes to which chapters, scenes and decisions can be reached.
+    pub reachability: Vec<String>,
+    /// How save files of the old questbook are affected when loaded with the new one.
+    pub saves: Vec<String>,
+}
+
+/// Differences in one section of a questbook, by ID.
+#[derive(Debug, Default)]
+pub struct SectionDiff {
+    pub section: &'static str,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// IDs of the changed entries, with their changes.
+    pub changed: Vec<(String, Vec<String>)>,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.story.is_empty()
+            && self.sections.iter().all(|s| s.added.is_empty() && s.removed.is_empty() && s.changed.is_empty())
+            && self.reachability.is_empty()
+            && self.saves.is_empty()
+    }
+}
+
+/**
+ * Compare two versions of a questbook.
+ */
+pub fn diff(old: &Questbook, new: &Questbook) -> BookDiff {
+    let sections = vec![
+        diff_section("chapters", &old.chapters, &new.chapters),
+        diff_section("scenes", &old.scenes, &new.scenes),
+        diff_section("decisions", &old.decisions, &new.decisions),
+        diff_section("consequences", &old.consequences, &new.consequences),
+        diff_section("triggers", &old.triggers, &new.triggers),
+        diff_section("items", &old.items, &new.items),
+        diff_section("recipes", &old.recipes, &new.recipes),
+        diff_section("statuses", &old.statuses, &new.statuses),
+        diff_section("counters", &old.counters, &new.counters),
+        diff_section("variables", &old.variables, &new.variables),
+        diff_section("characters", &old.characters, &new.characters),
+        diff_section("endings", &old.endings, &new.endings),
+        diff_section("achievements", &old.achievements, &new.achievements),
+        diff_section("quests", &old.quests, &new.quests),
+    ];
+    BookDiff {
+        story: diff_entry(&old.story, &new.story),
+        sections,
+        reachability: diff_reachability(old, new),
+        saves: diff_saves(old, new),
+    }
+}
+
+fn diff_section<T: Serialize + PartialEq>(section: &'static str, old: &HashMap<String, T>, new: &HashMap<String, T>) -> SectionDiff {
+    let mut diff = SectionDiff { section, ..Default::default() };
+    for (id, entry) in new.iter() {
+        match old.get(id) {
+            None => diff.added.push(id.clone()),
+            Some(previous) if previous != entry => diff.changed.push((id.clone(), diff_entry(previous, entry))),
+            Some(_) => {},
+        }
+    }
+    diff.removed = old.keys().filter(|id| !new.contains_key(*id)).cloned().collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    diff
+}
+
+/**
+ * Describe the changes to each field of an entry.
+ */
+fn diff_entry<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    let (old, new) = match (serde_yaml::to_value(old), serde_yaml::to_value(new)) {
+        (Ok(Value::Mapping(old)), Ok(Value::Mapping(new))) => (old, new),
+        _ => return Vec::new(),
+    };
+    let mut changes = Vec::new();
+    let keys = old.keys().chain(new.keys().filter(|k| !old.contains_key(*k)));
+    for key in keys {
+        let field = key.as_str().unwrap_or_default();
+        match (old.get(key), new.get(key)) {
+            (Some(before), Some(after)) if before == after => {},
+            (Some(before), Some(after)) if field == "requirements" => {
+                changes.extend(diff_requirements(before, after));
+            },
+            (Some(before), Some(after)) => match (short(before), short(after)) {
+                (Some(before), Some(after)) => changes.push(format!("{}: {} -> {}", field, before, after)),
+                _ => changes.push(format!("{} changed", field)),
+            },
+            (None, Some(after)) if field == "requirements" => changes.extend(diff_requirements(&Value::Null, after)),
+            (Some(before), None) if field == "requirements" => changes.extend(diff_requirements(before, &Value::Null)),
+            (None, Some(_)) => changes.push(format!("{} added", field)),
+            (Some(_), None) => changes.push(format!("{} removed", field)),
+            (None, None) => {},
+        }
+    }
+    changes
+}
+
+fn diff_requirements(old: &Value, new: &Value) -> Vec<String> {
+    let requirements = |value: &Value| -> Vec<String> {
+        serde_yaml::from_value::<Vec<Requirement>>(value.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|r| format!("{:?}", r).replace('"', ""))
+            .collect()
+    };
+    let (old, new) = (requirements(old), requirements(new));
+    let mut changes: Vec<String> = old.iter()
+        .filter(|r| !new.contains(r))
+        .map(|r| format!("requirement removed: {}", r))
+        .collect();
+    changes.extend(new.iter().filter(|r| !old.contains(r)).map(|r| format!("requirement added: {}", r)));
+    changes
+}
+
+/**
+ * A value on one line, if it is short enough to show in a change.
+ */
+fn short(value: &Value) -> Option<String> {
+    if matches!(value, Value::Mapping(_) | Value::Tagged(_)) {
+        return None;
+    }
+    let text = serde_json::to_string(value).ok()?;
+    (text.len() <= 40 && !text.contains("\\n")).then_some(text)
+}
+
+/// What can be reached in a questbook, from what the consequences can provide.
+#[derive(Debug, Default)]
+pub struct Reachable {
+    pub chapters: HashSet<String>,
+    pub scenes: HashSet<String>,
+    pub decisions: HashSet<String>,
+    pub consequences: HashSet<String>,
+    pub endings: HashSet<String>,
+    /// IDs of the items, statuses and tags that can be provided.
+    pub provided: HashSet<String>,
+}
+
+/**
+ * Find what can be reached in a questbook by following the story, assuming every
+ * requirement can be met except needing something that is never provided.
+ */
+pub fn reachable(questbook: &Questbook) -> Reachable {
+    let mut reach = Reachable::default();
+    let creation = questbook.story.creation.iter().flat_map(|c| c.choices.iter().flatten());
+    for option in creation.flat_map(|c| c.options.iter()) {
+        reach.provided.extend(option.provides.iter().flatten().cloned());
+    }
+    loop {
+        let before = reach.consequences.len() + reach.provided.len();
+        let mut consequences: Vec<&String> = Vec::new();
+
+        for chapter_id in questbook.story.chapters.iter() {
+            let chapter = match questbook.chapters.get(chapter_id) {
+                Some(chapter) if reach.possible(chapter.requirements.as_deref()) => chapter,
+                _ => continue,
+            };
+            reach.chapters.insert(chapter_id.clone());
+            consequences.extend(chapter.on_enter.iter().flatten());
+            consequences.extend(chapter.hints.iter().flatten().flat_map(|hint| hint.cost.iter().flatten()));
+            for scene_id in chapter.scenes.iter() {
+                if let Some(scene) = questbook.scenes.get(scene_id).filter(|s| reach.possible(s.requirements.as_deref())) {
+                    reach.scenes.insert(scene_id.clone());
+                    consequences.extend(scene.on_enter.iter().flatten().chain(scene.on_exit.iter().flatten()));
+                    consequences.extend(scene.hints.iter().flatten().flat_map(|hint| hint.cost.iter().flatten()));
+                    for decision in scene.decisions.iter() {
+                        consequences.extend(reach.decide(questbook, decision));
+                    }
+                }
+            }
+        }
+        for decision in questbook.story.decisions.iter().flatten() {
+            consequences.extend(reach.decide(questbook, decision));
+        }
+        for (id, item) in questbook.items.iter() {
+            if reach.provided.contains(id) {
+                consequences.extend(item.on_use.iter().flatten());
+            }
+        }
+        for recipe in questbook.recipes.values() {
+            if recipe.ingredients.iter().all(|i| reach.provided.contains(i)) {
+                consequences.extend(recipe.consequences.iter());
+            }
+        }
+        for trigger in questbook.triggers.values() {
+            if reach.possible(Some(&trigger.requirements)) {
+                consequences.extend(trigger.consequences.iter());
+            }
+        }
+
+        while let Some(id) = consequences.pop() {
+            let consequence = match questbook.consequences.get(id) {
+                Some(consequence) => consequence,
+                None => continue,
+            };
+            if !reach.consequences.insert(id.clone()) {
+                continue;
+            }
+            reach.provided.extend(consequence.provides.iter().flatten().cloned());
+            reach.endings.extend(consequence.ending.iter().cloned());
+            consequences.extend(consequence.schedule.iter().flatten().map(|(id, _)| id));
+        }
+
+        if reach.consequences.len() + reach.provided.len() == before {
+            return reach;
+        }
+    }
+}
+
+impl Reachable {
+    /**
+     * Whether requirements could be met, from what can be provided so far.
+     */
+    fn possible(&self, requirements: Option<&[Requirement]>) -> bool {
+        requirements.into_iter().flatten().all(|requirement| match requirement {
+            Requirement::Require(id) | Requirement::RequireCount(id, _) => self.provided.contains(id),
+            _ => true,
+        })
+    }
+
+    /**
+     * Mark a decision as reached if it's possible, returning the consequences it can lead to.
+     */
+    fn decide<'a>(&mut self, questbook: &'a Questbook, decision_id: &str) -> Vec<&'a String> {
+        let decision = match questbook.decisions.get(decision_id) {
+            Some(decision) if self.possible(decision.requirements.as_deref()) => decision,
+            _ => return Vec::new(),
+        };
+        self.decisions.insert(decision_id.to_string());
+        let mut consequences: Vec<&String> = decision.consequences.iter().collect();
+        consequences.extend(decision.outcomes.iter().flatten().flat_map(|o| o.consequences.iter()));
+        if let Some(check) = &decision.check {
+            consequences.extend(check.success.iter().chain(check.failure.iter()));
+        }
+        if let Some(prompt) = &decision.prompt {
+            consequences.extend(prompt.success.iter().chain(prompt.failure.iter()));
+        }
+        consequences
+    }
+}
+
+fn diff_reachability(old: &Questbook, new: &Questbook) -> Vec<String> {
+    let (before, after) = (reachable(old), reachable(new));
+    let mut changes = Vec::new();
+    let kinds = [
+        ("Chapter", &before.chapters, &after.chapters, &old.chapters.keys().collect::<HashSet<_>>(), &new.chapters.keys().collect::<HashSet<_>>()),
+        ("Scene", &before.scenes, &after.scenes, &old.scenes.keys().collect(), &new.scenes.keys().collect()),
+        ("Decision", &before.decisions, &after.decisions, &old.decisions.keys().collect(), &new.decisions.keys().collect()),
+        ("Ending", &before.endings, &after.endings, &old.endings.keys().collect(), &new.endings.keys().collect()),
+    ];
+    for (kind, before, after, old_ids, new_ids) in kinds {
+        let mut ids: Vec<&&String> = new_ids.iter().collect();
+        ids.sort();
+        for id in ids {
+            match (old_ids.contains(*id), before.contains(*id), after.contains(*id)) {
+                (true, true, false) => changes.push(format!("{} {} can no longer be reached", kind, id)),
+                (true, false, true) => changes.push(format!("{} {} can now be reached", kind, id)),
+                (false, _, false) => changes.push(format!("{} {} was added, but can't be reached", kind, id)),
+                _ => {},
+            }
+        }
+    }
+    changes
+}
+
+fn diff_saves(old: &Questbook, new: &Questbook) -> Vec<String> {
+    let mut changes = Vec::new();
+    for id in sorted(old.chapters.keys().filter(|id| !new.chapters.contains_key(*id))) {
+        changes.push(format!("Saves in chapter {} will move to the latest available chapter", id));
+    }
+    for id in sorted(old.scenes.keys().filter(|id| !new.scenes.contains_key(*id))) {
+        changes.push(format!("Saves in scene {} will move to the first available scene", id));
+    }
+    for id in sorted(old.items.keys().filter(|id| !new.items.contains_key(*id))) {
+        changes.push(format!("Saves holding item {} will lose it", id));
+    }
+    for id in sorted(old.statuses.keys().filter(|id| !new.statuses.contains_key(*id))) {
+        changes.push(format!("Saves with status {} will lose it", id));
+    }
+    for id in sorted(old.counters.keys().filter(|id| !new.counters.contains_key(*id))) {
+        changes.push(format!("Counter {} will be removed from saves", id));
+    }
+    for id in sorted(new.counters.keys().filter(|id| !old.counters.contains_key(*id))) {
+        changes.push(format!("Counter {} will be added to saves, set to {}", id, new.counters[id].value));
+    }
+    for id in sorted(old.variables.keys().filter(|id| !new.variables.contains_key(*id))) {
+        changes.push(format!("Variable {} will be removed from saves", id));
+    }
+    for id in sorted(new.variables.keys().filter(|id| !old.variables.contains_key(*id))) {
+        changes.push(format!("Variable {} will be added to saves, set to {:?}", id, new.variables[id].value));
+    }
+    let scheduled: HashSet<&String> = old.consequences.values()
+        .flat_map(|c| c.schedule.iter().flatten().map(|(id, _)| id))
+        .collect();
+    for id in sorted(scheduled.into_iter().filter(|id| !new.consequences.contains_key(*id))) {
+        changes.push(format!("Saves waiting on scheduled consequence {} will have it cancelled", id));
+    }
+    let provided = |questbook: &Questbook| -> HashSet<String> {
+        questbook.consequences.values().flat_map(|c| c.provides.iter().flatten().cloned()).collect()
+    };
+    let still_provided = provided(new);
+    let tags = provided(old).into_iter()
+        .filter(|id| !old.items.contains_key(id) && !old.statuses.contains_key(id) && !still_provided.contains(id));
+    for id in sorted(tags.collect::<Vec<_>>().iter()) {
+        changes.push(format!("Saves may have tag {}, which nothing provides anymore", id));
+    }
+    changes
+}
+
+fn sorted<'a, T: AsRef<str> + 'a>(ids: impl Iterator<Item = &'a T>) -> Vec<&'a str> {
+    let mut ids: Vec<&str> = ids.map(|id| id.as_ref()).collect();
+    ids.sort();
+    ids
+}
+
+impl fmt::Display for BookDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        let mut blocks = Vec::new();
+        if !self.story.is_empty() {
+            blocks.push(format!("story\n{}", lines(&self.story, "  ")));
+        }
+        for section in self.sections.iter() {
+            let mut block = Vec::new();
+            block.extend(section.added.iter().map(|id| format!("  + {}", id)));
+            block.extend(section.removed.iter().map(|id| format!("  - {}", id)));
+            for (id, changes) in section.changed.iter() {
+                block.push(format!("  ~ {}", id));
+                block.extend(changes.iter().map(|change| format!("      {}", change)));
+            }
+            if !block.is_empty() {
+                blocks.push(format!("{}\n{}", section.section, block.join("\n")));
+            }
+        }
+        if !self.reachability.is_empty() {
+            blocks.push(format!("reachability\n{}", lines(&self.reachability, "  ")));
+        }
+        if !self.saves.is_empty() {
+            blocks.push(format!("saves\n{}", lines(&self.saves, "  ")));
+        }
+        writeln!(f, "{}", blocks.join("\n\n"))
+    }
+}
+
+fn lines(lines: &[String], indent: &str) -> String {
+    lines.iter().map(|line| format!("{}{}", indent, line)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "
+story:
+  title: Test
+  chapters: [start]
+chapters:
+  start:
+    title: Start
+    scenes: [room, hall]
+scenes:
+  room:
+    name: Room
+    background: A room.
+    decisions: [open]
+  hall:
+    name: Hall
+    background: A hall.
+    decisions: []
+    requirements:
+      - !Require key
+items:
+  key:
+    name: Key
+    description: A key.
+decisions:
+  open:
+    decision: Open the door.
+    consequences: [opened]
+characters:
+consequences:
+  opened:
+    conclusion: The door opens.
+    provides: [key]
+statuses:
+counters:
+  gold:
+    name: Gold
+    value: 5
+    visible: true
+triggers:
+";
+
+    fn book(yaml: &str) -> Questbook {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn section<'a>(diff: &'a BookDiff, name: &str) -> &'a SectionDiff {
+        diff.sections.iter().find(|s| s.section == name).unwrap()
+    }
+
+    #[test]
+    fn identical_questbooks_have_no_changes() {
+        let diff = diff(&book(BOOK), &book(BOOK));
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No changes\n");
+    }
+
+    #[test]
+    fn added_entries_are_listed() {
+        let new = BOOK.replace("characters:\n", "  wait:\n    decision: Wait.\n    consequences: [opened]\ncharacters:\n");
+        let diff = diff(&book(BOOK), &book(&new));
+        assert_eq!(section(&diff, "decisions").added, vec!["wait"]);
+        assert!(section(&diff, "decisions").removed.is_empty());
+        assert_eq!(diff.reachability, vec!["Decision wait was added, but can't be reached"]);
+        assert!(diff.saves.is_empty());
+    }
+
+    #[test]
+    fn removed_entries_are_listed_with_their_impact_on_saves() {
+        let new = BOOK
+            .replace("    scenes: [room, hall]\n", "    scenes: [room]\n")
+            .replace("  hall:\n    name: Hall\n    background: A hall.\n    decisions: []\n    requirements:\n      - !Require key\n", "")
+            .replace("counters:\n  gold:\n    name: Gold\n    value: 5\n    visible: true\n", "counters:\n");
+        let diff = diff(&book(BOOK), &book(&new));
+        assert_eq!(section(&diff, "scenes").removed, vec!["hall"]);
+        assert_eq!(section(&diff, "counters").removed, vec!["gold"]);
+        assert_eq!(section(&diff, "chapters").changed, vec![("start".to_string(), vec![r#"scenes: ["room","hall"] -> ["room"]"#.to_string()])]);
+        assert_eq!(diff.saves, vec![
+            "Saves in scene hall will move to the first available scene",
+            "Counter gold will be removed from saves",
+        ]);
+    }
+
+    #[test]
+    fn renamed_entries_are_removed_and_added() {
+        let new = BOOK.replace("start", "opening");
+        let diff = diff(&book(BOOK), &book(&new));
+        assert_eq!(section(&diff, "chapters").added, vec!["opening"]);
+        assert_eq!(section(&diff, "chapters").removed, vec!["start"]);
+        assert_eq!(diff.story, vec![r#"chapters: ["start"] -> ["opening"]"#]);
+        assert_eq!(diff.saves, vec!["Saves in chapter start will move to the latest available chapter"]);
+    }
+
+    #[test]
+    fn changed_requirements_are_listed() {
+        let new = BOOK.replace("      - !Require key\n", "      - !Refuse key\n");
+        let diff = diff(&book(BOOK), &book(&new));
+        assert_eq!(section(&diff, "scenes").changed, vec![("hall".to_string(), vec![
+            "requirement removed: Require(key)".to_string(),
+            "requirement added: Refuse(key)".to_string(),
+        ])]);
+    }
+
+    #[test]
+    fn items_and_counters_that_change_affect_saves() {
+        let new = BOOK
+            .replace("items:\n  key:\n    name: Key\n    description: A key.\n", "items:\n")
+            .replace("    provides: [key]\n", "")
+            .replace("counters:\n  gold:\n    name: Gold\n    value: 5\n    visible: true\n", "counters:\n  silver:\n    name: Silver\n    value: 2\n    visible: true\n");
+        let diff = diff(&book(BOOK), &book(&new));
+        assert_eq!(diff.saves, vec![
+            "Saves holding item key will lose it",
+            "Counter gold will be removed from saves",
+            "Counter silver will be added to saves, set to 2",
+        ]);
+        assert_eq!(diff.reachability, vec!["Scene hall can no longer be reached"]);
+    }
+}
@@ -0,0 +1,1052 @@
+use crate::book::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use serde_yaml::{Mapping, Value};
+
+/// Text is wrapped to fit in this many columns where it can be.
+const WIDTH: usize = 80;
+
+/// Comments found in a questbook file, keyed by the path of keys and list indexes to what
+/// they belong to, like `["scenes", "start", "decisions", "0"]`.
+#[derive(Debug, Default)]
+struct Comments {
+    /// Comments on their own lines before a section, entry, field or list item.
+    before: HashMap<Vec<String>, Vec<Comment>>,
+    /// Comments at the end of the line a section, entry, field or list item starts on.
+    after: HashMap<Vec<String>, Comment>,
+    /// Comments after everything else.
+    trailing: Vec<Comment>,
+    /// Comments that aren't on or before a section, entry, field or list item.
+    unplaced: Vec<Comment>,
+}
+
+#[derive(Debug)]
+struct Comment {
+    /// Line of the source the comment is on.
+    line: usize,
+    /// The comment, starting from the `#`.
+    text: String,
+}
+
+/**
+ * Rewrite the source of a questbook in the canonical layout: sections in the order of the
+ * `Questbook` fields, entries in the order they're first referenced, two space indentation,
+ * and long text wrapped. Comments stay with the section, entry, field or list item they're
+ * on or above, and formatting fails if one can't be kept.
+ */
+pub fn format_questbook(source: &str) -> Result<String, String> {
+    let questbook: Questbook = serde_yaml::from_str(source).map_err(|e| e.to_string())?;
+    rewrite_questbook(source, &questbook)
+}
+
+/**
+ * Write a questbook in the canonical layout, keeping the comments from the source it was
+ * loaded from. Used to save changes made to a loaded questbook.
+ */
+pub fn rewrite_questbook(source: &str, questbook: &Questbook) -> Result<String, String> {
+    let order = reference_order(questbook);
+    let value = serde_yaml::to_value(questbook).map_err(|e| e.to_string())?;
+    let sections = match &value {
+        Value::Mapping(sections) => sections,
+        _ => return Err("Questbook isn't a mapping".to_string()),
+    };
+
+    let mut writer = Writer {
+        out: String::new(),
+        comments: find_comments(source),
+    };
+    for (key, section) in sections.iter() {
+        let name = key.as_str().unwrap_or_default().to_string();
+        let path = vec![name.clone()];
+        let has_comments = writer.comments.before.contains_key(&path) || writer.comments.after.contains_key(&path);
+        let is_empty = matches!(section, Value::Mapping(m) if m.is_empty());
+        if is_empty && !has_comments && !is_required_section(&name) {
+            continue;
+        }
+
+        if !writer.out.is_empty() {
+            writer.out.push('\n');
+        }
+        match section {
+            Value::Mapping(entries) if name != "story" => {
+                writer.key_line(0, &path, &format!("{}:", name));
+                let mut path = path;
+                for id in ordered_keys(entries, order.get(name.as_str())) {
+                    path.push(id.clone());
+                    writer.field(1, &Value::String(id.clone()), &entries[id.as_str()], &mut path);
+                    path.pop();
+                }
+            },
+            Value::Mapping(_) => {
+                let mut path = path;
+                writer.field(0, key, section, &mut path);
+            },
+            _ => return Err(format!("Section {} isn't a mapping", name)),
+        }
+    }
+    let trailing = std::mem::take(&mut writer.comments.trailing);
+    if !trailing.is_empty() {
+        writer.out.push('\n');
+        for comment in trailing {
+            writer.out.push_str(&comment.text);
+            writer.out.push('\n');
+        }
+    }
+
+    // Never lose a comment.
+    let mut left: Vec<Comment> = writer.comments.before.into_values().flatten()
+        .chain(writer.comments.after.into_values())
+        .chain(writer.comments.unplaced)
+        .collect();
+    left.sort_by_key(|comment| comment.line);
+    if let Some(comment) = left.first() {
+        return Err(format!("Comment on line {} can't be kept when formatting: {}", comment.line, comment.text));
+    }
+
+    // Never change what the questbook means.
+    let out = writer.out;
+    let reformatted: Questbook = serde_yaml::from_str(&out).map_err(|e| format!("Formatted questbook can't be read: {}", e))?;
+    if serde_yaml::to_value(&reformatted).map_err(|e| e.to_string())? != value {
+        return Err("Formatting would change the questbook".to_string());
+    }
+    Ok(out)
+}
+
+/**
+ * Add the new entry `id` to a section of the source of a questbook, and add its ID to the
+ * list at `list` if there is one, like `["chapters", "start", "scenes"]`. `questbook` has
+ * both changes made. The entry goes at the end of its section and the ID at the end of the
+ * list, which is rewritten in the canonical layout if it isn't a block list already,
+ * leaving the rest of the source as it was.
+ */
+pub fn insert_entry(source: &str, questbook: &Questbook, section: &str, id: &str, list: Option<&[String]>) -> Result<String, String> {
+    let value = serde_yaml::to_value(questbook).map_err(|e| e.to_string())?;
+    let lines = scan_source(source);
+    let mut text: Vec<String> = source.lines().map(str::to_string).collect();
+    let mut writer = Writer {
+        out: String::new(),
+        comments: find_comments(source),
+    };
+    // Lines to replace, as the first line, how many lines, and the lines to put there.
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+    if let Some(list) = list {
+        let ids = lookup(&value, list).ok_or_else(|| format!("There's no {}", list.join(".")))?;
+        let mut path = list.to_vec();
+        match value_lines(&lines, list) {
+            Some((start, end)) if child_column(&lines[start..=end], list).is_some() => {
+                // A block list only needs the ID added to the end.
+                let column = child_column(&lines[start..=end], list).unwrap_or_default();
+                edits.push((end + 1, 0, format!("{}- {}", " ".repeat(column), scalar(&Value::String(id.to_string())))));
+            },
+            Some((start, end)) => {
+                // Comments above the list aren't replaced.
+                writer.comments.before.remove(list);
+                let column = lines[start].steps[list.len() - 1].0;
+                let written = writer.standalone_field(&text[start][..column], column, &mut path, ids);
+                edits.push((start, end + 1 - start, written));
+            },
+            None => {
+                let parent = &list[..list.len() - 1];
+                let (start, end) = value_lines(&lines, parent).ok_or_else(|| format!("There's no {}", parent.join(".")))?;
+                let column = child_column(&lines[start..=end], parent).unwrap_or(lines[start].steps[parent.len() - 1].0 + 2);
+                let written = writer.standalone_field(&" ".repeat(column), column, &mut path, ids);
+                edits.push((end + 1, 0, written));
+            },
+        }
+    }
+
+    let path = vec![section.to_string()];
+    let entry = lookup(&value, &[section.to_string(), id.to_string()]).ok_or_else(|| format!("There's no {} {}", section, id))?;
+    let (start, end) = value_lines(&lines, &path).ok_or_else(|| format!("There's no {} section", section))?;
+    let column = child_column(&lines[start..=end], &path).unwrap_or(2);
+    let mut entry_path = vec![section.to_string(), id.to_string()];
+    let written = writer.standalone_field(&" ".repeat(column), column, &mut entry_path, entry);
+    edits.push((end + 1, 0, written));
+    // An empty section written as `{}` becomes a block with the entry in it.
+    let value_start = text[start].find(':').map(|colon| colon + 1).unwrap_or(text[start].len());
+    let value_end = lines[start].comment.unwrap_or(text[start].len());
+    if value_start < value_end && !text[start][value_start..value_end].trim().is_empty() {
+        let comment = lines[start].comment.map(|c| format!("  {}", &text[start][c..])).unwrap_or_default();
+        edits.push((start, 1, format!("{}:{}", section, comment)));
+    }
+
+    edits.sort_by_key(|(start, _, _)| *start);
+    for (start, count, written) in edits.into_iter().rev() {
+        text.splice(start..start + count, written.lines().map(str::to_string));
+    }
+    let mut out = text.join("\n");
+    if source.ends_with('\n') || source.is_empty() {
+        out.push('\n');
+    }
+
+    let added: Questbook = serde_yaml::from_str(&out).map_err(|e| format!("Questbook with the new entry can't be read: {}", e))?;
+    if serde_yaml::to_value(&added).map_err(|e| e.to_string())? != value {
+        return Err(format!("Adding {} {} would change the rest of the questbook", section, id));
+    }
+    Ok(out)
+}
+
+/**
+ * The value at a path of keys in a YAML mapping.
+ */
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.as_mapping()?.get(key.as_str()))
+}
+
+/**
+ * The first and last lines of the value at `path`, from the line its key is on to the last
+ * line inside it.
+ */
+fn value_lines(lines: &[SourceLine], path: &[String]) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| {
+        !line.continued && line.first.is_some_and(|first| first <= path.len()) && line.steps.len() >= path.len() && line.path(path.len()) == path
+    })?;
+    let end = (start..lines.len())
+        .take_while(|&index| index == start || !lines[index].content || lines[index].is_under(path))
+        .filter(|&index| lines[index].content)
+        .last()?;
+    Some((start, end))
+}
+
+/**
+ * The column the keys inside the mapping at `path` start at, if it has any.
+ */
+fn child_column(lines: &[SourceLine], path: &[String]) -> Option<usize> {
+    lines.iter().find(|line| line.steps.len() > path.len() && line.is_under(path)).map(|line| line.steps[path.len()].0)
+}
+
+/**
+ * Sections that are always written, because the questbook can't be read without them.
+ */
+fn is_required_section(name: &str) -> bool {
+    matches!(name, "story" | "chapters" | "scenes" | "items" | "decisions" | "characters" | "consequences" | "statuses" | "counters" | "triggers")
+}
+
+/// A step in the path to a value: a key of a mapping or an index of a list.
+#[derive(Debug)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl Step {
+    fn name(&self) -> String {
+        match self {
+            Step::Key(key) => key.clone(),
+            Step::Index(index) => index.to_string(),
+        }
+    }
+}
+
+/// Where a line of source is, for the lines after it.
+#[derive(Debug, Default)]
+struct LineState {
+    /// Quote a string is in that goes on to the next line.
+    quote: Option<char>,
+    /// How deep in flow lists and mappings, like `[a, b]`, the end of the line is.
+    depth: usize,
+    /// Whether the line starts a block of text, like `text: |`.
+    block: bool,
+}
+
+impl LineState {
+    fn continues(&self) -> bool {
+        self.quote.is_some() || self.depth > 0
+    }
+}
+
+/// A line of YAML source, and where it is in the document.
+#[derive(Debug)]
+struct SourceLine {
+    /// Keys and list indexes leading to the line, with the columns they start at. Lines
+    /// without a key or list item of their own have the path of the line before.
+    steps: Vec<(usize, String)>,
+    /// How many of `steps` lead to the first key or list item that starts on the line.
+    first: Option<usize>,
+    /// Where the comment on the line starts, if it has one.
+    comment: Option<usize>,
+    /// Whether the line is part of a value started on an earlier line, like text in a block.
+    continued: bool,
+    /// Whether the line has anything other than a comment.
+    content: bool,
+}
+
+impl SourceLine {
+    /**
+     * The first `len` steps of the path to the line.
+     */
+    fn path(&self, len: usize) -> Vec<String> {
+        self.steps[..len].iter().map(|(_, step)| step.clone()).collect()
+    }
+
+    fn is_under(&self, path: &[String]) -> bool {
+        self.steps.len() >= path.len() && self.steps.iter().zip(path).all(|((_, step), key)| step == key)
+    }
+}
+
+/**
+ * Work out where each line of YAML source is in the document.
+ */
+fn scan_source(source: &str) -> Vec<SourceLine> {
+    let mut lines = Vec::new();
+    // Keys and list indexes leading to the current line, with the columns they start at.
+    let mut steps: Vec<(usize, Step)> = Vec::new();
+    let mut state = LineState::default();
+    // Lines indented past this column are text in a block.
+    let mut block_column: Option<usize> = None;
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let in_block = block_column.is_some_and(|column| trimmed.is_empty() || indent > column);
+        if !in_block {
+            block_column = None;
+        }
+        let continued = in_block || state.continues();
+        let mut first = None;
+        let mut comment = None;
+        if !in_block && !trimmed.is_empty() {
+            if !continued && !trimmed.starts_with('#') {
+                let mut column = indent;
+                let mut rest = trimmed;
+                loop {
+                    if rest == "-" || rest.starts_with("- ") {
+                        while steps.last().is_some_and(|(c, _)| *c > column) {
+                            steps.pop();
+                        }
+                        match steps.last_mut() {
+                            Some((c, Step::Index(index))) if *c == column => *index += 1,
+                            _ => steps.push((column, Step::Index(0))),
+                        }
+                        first.get_or_insert(steps.len());
+                        let after = &rest[1..];
+                        column += 1 + after.len() - after.trim_start().len();
+                        rest = after.trim_start();
+                        continue;
+                    }
+                    if let Some(key) = mapping_key(rest) {
+                        while steps.last().is_some_and(|(c, _)| *c >= column) {
+                            steps.pop();
+                        }
+                        steps.push((column, Step::Key(key)));
+                        first.get_or_insert(steps.len());
+                    }
+                    break;
+                }
+            }
+            comment = scan_line(trimmed, &mut state).map(|start| indent + start);
+            if state.block {
+                block_column = Some(steps.last().map(|(column, _)| *column).unwrap_or(indent));
+            }
+        }
+        lines.push(SourceLine {
+            steps: steps.iter().map(|(column, step)| (*column, step.name())).collect(),
+            first,
+            comment,
+            continued,
+            content: !trimmed.is_empty() && comment != Some(indent),
+        });
+    }
+    lines
+}
+
+/**
+ * Find comment lines and what they belong to.
+ */
+fn find_comments(source: &str) -> Comments {
+    let mut comments = Comments::default();
+    let mut pending: Vec<Comment> = Vec::new();
+
+    for (number, (text, line)) in source.lines().zip(scan_source(source)).enumerate() {
+        let comment = line.comment.map(|start| Comment {
+            line: number + 1,
+            text: text[start..].trim_end().to_string(),
+        });
+        match (comment, line.first) {
+            (Some(comment), _) if line.continued => comments.unplaced.push(comment),
+            (Some(comment), _) if !line.content => pending.push(comment),
+            (Some(comment), Some(first)) => {
+                if !pending.is_empty() {
+                    comments.before.entry(line.path(first)).or_default().append(&mut pending);
+                }
+                match comments.after.entry(line.path(line.steps.len())) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(comment);
+                    },
+                    Entry::Occupied(_) => comments.unplaced.push(comment),
+                }
+            },
+            (Some(comment), None) => comments.unplaced.push(comment),
+            (None, Some(first)) if !pending.is_empty() => {
+                comments.before.entry(line.path(first)).or_default().append(&mut pending);
+            },
+            (None, _) => {},
+        }
+    }
+    comments.trailing = pending;
+    comments
+}
+
+/**
+ * The key a line of a block mapping starts with, like `title` in `title: The Tomb`.
+ */
+fn mapping_key(text: &str) -> Option<String> {
+    if text.starts_with(['[', '{', '\'', '"', '!', '|', '>', '#', '&', '*', '?']) {
+        return None;
+    }
+    let end = text.char_indices()
+        .find(|&(i, c)| c == ':' && text[i + 1..].chars().next().is_none_or(char::is_whitespace))
+        .map(|(i, _)| i)?;
+    let key = text[..end].trim();
+    (!key.contains(" #")).then(|| key.to_string())
+}
+
+/**
+ * Find where the comment on a line of YAML starts, if it has one, keeping track of quotes
+ * and flow lists that go on to the next line.
+ */
+fn scan_line(line: &str, state: &mut LineState) -> Option<usize> {
+    state.block = false;
+    let mut value_start = !state.continues() || state.depth > 0;
+    let mut previous: Option<char> = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        match state.quote {
+            Some('\'') => {
+                if c == '\'' && next == Some('\'') {
+                    chars.next();
+                } else if c == '\'' {
+                    state.quote = None;
+                }
+            },
+            Some(_) => {
+                if c == '\\' {
+                    chars.next();
+                } else if c == '"' {
+                    state.quote = None;
+                }
+            },
+            None => match c {
+                '#' if previous.is_none_or(char::is_whitespace) => return Some(i),
+                _ if c.is_whitespace() => {},
+                '\'' | '"' if value_start => {
+                    state.quote = Some(c);
+                    value_start = false;
+                },
+                '-' | '?' if value_start && next.is_none_or(char::is_whitespace) => {},
+                ':' if next.is_none_or(char::is_whitespace) || state.depth > 0 => value_start = true,
+                '[' | '{' if value_start || state.depth > 0 => state.depth += 1,
+                ']' | '}' if state.depth > 0 => {
+                    state.depth -= 1;
+                    value_start = false;
+                },
+                ',' if state.depth > 0 => value_start = true,
+                '!' if value_start => {
+                    while chars.next_if(|&(_, c)| !c.is_whitespace()).is_some() {}
+                },
+                '|' | '>' if value_start && state.depth == 0 => {
+                    while chars.next_if(|&(_, c)| !c.is_whitespace()).is_some() {}
+                    state.block = true;
+                    value_start = false;
+                },
+                _ => value_start = false,
+            },
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+/**
+ * The IDs referenced in a questbook, by section, in the order they're first referenced when
+ * reading through the story.
+ */
+fn reference_order(questbook: &Questbook) -> HashMap<&'static str, Vec<String>> {
+    let mut walker = Walker {
+        questbook,
+        order: HashMap::new(),
+        seen: HashSet::new(),
+    };
+    if let Some(creation) = &questbook.story.creation {
+        if let Some(variable) = &creation.name {
+            walker.note("variables", variable);
+        }
+        for option in creation.choices.iter().flatten().flat_map(|c| c.options.iter()) {
+            for thing in option.provides.iter().flatten() {
+                walker.thing(thing);
+            }
+        }
+        for counter in creation.points.iter().flat_map(|p| p.counters.iter()) {
+            walker.note("counters", counter);
+        }
+    }
+    for chapter in questbook.story.chapters.iter() {
+        walker.chapter(chapter);
+    }
+    for decision in questbook.story.decisions.iter().flatten() {
+        walker.decision(decision);
+    }
+    for item in sorted(questbook.items.keys()) {
+        walker.item(item);
+    }
+    for recipe in sorted(questbook.recipes.keys()) {
+        walker.note("recipes", recipe);
+        for consequence in questbook.recipes[recipe].consequences.iter() {
+            walker.consequence(consequence);
+        }
+    }
+    for trigger in sorted(questbook.triggers.keys()) {
+        walker.note("triggers", trigger);
+        walker.requirements(Some(&questbook.triggers[trigger].requirements));
+        for consequence in questbook.triggers[trigger].consequences.iter() {
+            walker.consequence(consequence);
+        }
+    }
+    walker.order
+}
+
+fn sorted<'a>(keys: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = keys.collect();
+    keys.sort();
+    keys
+}
+
+/**
+ * Keys of a section, in reference order and then alphabetically.
+ */
+fn ordered_keys(entries: &Mapping, order: Option<&Vec<String>>) -> Vec<String> {
+    let mut keys: Vec<String> = order.into_iter().flatten()
+        .filter(|id| entries.contains_key(id.as_str()))
+        .cloned()
+        .collect();
+    let mut rest: Vec<String> = entries.keys()
+        .filter_map(|k| k.as_str())
+        .filter(|k| !keys.iter().any(|id| id == k))
+        .map(|k| k.to_string())
+        .collect();
+    rest.sort();
+    keys.extend(rest);
+    keys
+}
+
+/// Walks a questbook in reading order, noting each ID the first time it's referenced.
+struct Walker<'a> {
+    questbook: &'a Questbook,
+    order: HashMap<&'static str, Vec<String>>,
+    seen: HashSet<(&'static str, String)>,
+}
+
+impl Walker<'_> {
+    /**
+     * Note a reference, returning false if it was already seen.
+     */
+    fn note(&mut self, section: &'static str, id: &str) -> bool {
+        if !self.seen.insert((section, id.to_string())) {
+            return false;
+        }
+        self.order.entry(section).or_default().push(id.to_string());
+        true
+    }
+
+    /**
+     * Note an ID that could be an item, status, or tag.
+     */
+    fn thing(&mut self, id: &str) {
+        if self.questbook.items.contains_key(id) {
+            self.item(id);
+        } else {
+            self.note("statuses", id);
+        }
+    }
+
+    fn requirements(&mut self, requirements: Option<&Vec<Requirement>>) {
+        for requirement in requirements.into_iter().flatten() {
+            match requirement {
+                Requirement::Require(id) | Requirement::Refuse(id) | Requirement::RequireCount(id, _) => self.thing(id),
+                Requirement::CounterEqual(id, _) | Requirement::CounterLessThan(id, _) | Requirement::CounterGreaterThan(id, _) => {
+                    self.note("counters", id);
+                },
+                Requirement::VariableEqual(id, _) | Requirement::VariableIn(id, _) => {
+                    self.note("variables", id);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn chapter(&mut self, id: &str) {
+        if !self.note("chapters", id) {
+            return;
+        }
+        if let Some(chapter) = self.questbook.chapters.get(id) {
+            self.requirements(chapter.requirements.as_ref());
+            let costs = chapter.hints.iter().flatten().flat_map(|hint| hint.cost.iter().flatten());
+            for consequence in chapter.on_enter.iter().flatten().chain(costs) {
+                self.consequence(consequence);
+            }
+            for scene in chapter.scenes.iter() {
+                self.scene(scene);
+            }
+        }
+    }
+
+    fn scene(&mut self, id: &str) {
+        if !self.note("scenes", id) {
+            return;
+        }
+        if let Some(scene) = self.questbook.scenes.get(id) {
+            self.requirements(scene.requirements.as_ref());
+            for character in scene.characters.iter().flatten() {
+                self.note("characters", character);
+            }
+            let costs = scene.hints.iter().flatten().flat_map(|hint| hint.cost.iter().flatten());
+            for consequence in scene.on_enter.iter().flatten().chain(scene.on_exit.iter().flatten()).chain(costs) {
+                self.consequence(consequence);
+            }
+            for decision in scene.decisions.iter() {
+                self.decision(decision);
+            }
+        }
+    }
+
+    fn decision(&mut self, id: &str) {
+        if !self.note("decisions", id) {
+            return;
+        }
+        if let Some(decision) = self.questbook.decisions.get(id) {
+            self.requirements(decision.requirements.as_ref());
+            let mut consequences: Vec<&String> = decision.consequences.iter().collect();
+            consequences.extend(decision.outcomes.iter().flatten().flat_map(|o| o.consequences.iter()));
+            if let Some(check) = &decision.check {
+                for counter in check.counters.iter().flatten() {
+                    self.note("counters", counter);
+                }
+                consequences.extend(check.success.iter().chain(check.failure.iter()));
+            }
+            if let Some(prompt) = &decision.prompt {
+                if let Some(variable) = &prompt.variable {
+                    self.note("variables", variable);
+                }
+                consequences.extend(prompt.success.iter().chain(prompt.failure.iter()));
+            }
+            for consequence in consequences {
+                self.consequence(consequence);
+            }
+        }
+    }
+
+    fn consequence(&mut self, id: &str) {
+        if !self.note("consequences", id) {
+            return;
+        }
+        if let Some(consequence) = self.questbook.consequences.get(id) {
+            for thing in consequence.provides.iter().flatten().chain(consequence.costs.iter().flatten()) {
+                self.thing(thing);
+            }
+            let counters = consequence.counter.iter().map(|(c, _)| c)
+                .chain(consequence.roll.iter().map(|(c, _)| c))
+                .chain(consequence.reset.iter().flatten());
+            for counter in counters {
+                self.note("counters", counter);
+            }
+            if let Some((variable, _)) = &consequence.variable {
+                self.note("variables", variable);
+            }
+            for (scheduled, _) in consequence.schedule.iter().flatten() {
+                self.consequence(scheduled);
+            }
+            if let Some(ending) = &consequence.ending {
+                self.note("endings", ending);
+            }
+            if let Some(scene) = &consequence.scene {
+                self.scene(scene);
+            }
+        }
+    }
+
+    fn item(&mut self, id: &str) {
+        if !self.note("items", id) {
+            return;
+        }
+        if let Some(item) = self.questbook.items.get(id) {
+            for consequence in item.on_use.iter().flatten() {
+                self.consequence(consequence);
+            }
+        }
+    }
+}
+
+fn indent(level: usize) -> String {
+    "  ".repeat(level)
+}
+
+/// Writes YAML in the canonical layout, putting comments back where they were.
+struct Writer {
+    out: String,
+    comments: Comments,
+}
+
+impl Writer {
+    /**
+     * Write the first line of what's at `path`, with the comments before and after it.
+     * Anything after the first line of `text` is written as it is.
+     */
+    fn key_line(&mut self, level: usize, path: &[String], text: &str) {
+        for comment in self.comments.before.remove(path).into_iter().flatten() {
+            self.out.push_str(&indent(level));
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+        }
+        let (first, rest) = match text.split_once('\n') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (text, None),
+        };
+        self.out.push_str(first);
+        if let Some(comment) = self.comments.after.remove(path) {
+            self.out.push_str("  ");
+            self.out.push_str(&comment.text);
+        }
+        self.out.push('\n');
+        if let Some(rest) = rest {
+            self.out.push_str(rest);
+            self.out.push('\n');
+        }
+    }
+
+    /**
+     * Write a field on its own, indented to `column`, with `lead` before its first line.
+     */
+    fn standalone_field(&mut self, lead: &str, column: usize, path: &mut Vec<String>, value: &Value) -> String {
+        let out = std::mem::take(&mut self.out);
+        let key = Value::String(path.last().cloned().unwrap_or_default());
+        self.field(0, &key, value, path);
+        let written = std::mem::replace(&mut self.out, out);
+        let indent = " ".repeat(column);
+        let lines: Vec<String> = written.lines().enumerate()
+            .map(|(index, line)| match index {
+                0 => format!("{}{}", lead, line),
+                _ if line.is_empty() => String::new(),
+                _ => format!("{}{}", indent, line),
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /**
+     * Write the fields of a mapping, one per line.
+     */
+    fn mapping(&mut self, level: usize, mapping: &Mapping, path: &mut Vec<String>) {
+        for (key, value) in mapping.iter() {
+            path.push(key_name(key));
+            self.field(level, key, value, path);
+            path.pop();
+        }
+    }
+
+    /**
+     * Write `key: value`, with the value on the following lines if it doesn't fit on one.
+     */
+    fn field(&mut self, level: usize, key: &Value, value: &Value, path: &mut Vec<String>) {
+        let prefix = format!("{}{}:", indent(level), scalar(key));
+        match value {
+            Value::Mapping(mapping) if !mapping.is_empty() => {
+                self.key_line(level, path, &prefix);
+                self.mapping(level + 1, mapping, path);
+            },
+            Value::Sequence(sequence) if !sequence.is_empty() && !is_flow(sequence) => {
+                self.key_line(level, path, &prefix);
+                self.sequence(level + 1, sequence, path);
+            },
+            Value::Null => self.key_line(level, path, &prefix),
+            _ => {
+                let text = format!("{}{}", prefix, inline(value, level, Some(prefix.len())));
+                self.key_line(level, path, &text);
+            },
+        }
+    }
+
+    /**
+     * Write the items of a block sequence.
+     */
+    fn sequence(&mut self, level: usize, sequence: &[Value], path: &mut Vec<String>) {
+        for (index, value) in sequence.iter().enumerate() {
+            path.push(index.to_string());
+            match value {
+                Value::Mapping(mapping) if !mapping.is_empty() => {
+                    // The first field goes on the same line as the dash, so comments before
+                    // it go before the item, and a comment after the dash can only stay
+                    // there if the first field doesn't have one.
+                    let mut first = path.clone();
+                    first.extend(mapping.keys().next().map(key_name));
+                    let mut moved = self.comments.before.remove(&first).unwrap_or_default();
+                    if self.comments.after.contains_key(&first) {
+                        moved.extend(self.comments.after.remove(path.as_slice()));
+                    }
+                    if !moved.is_empty() {
+                        self.comments.before.entry(path.clone()).or_default().extend(moved);
+                    }
+                    let out = std::mem::take(&mut self.out);
+                    self.mapping(level + 1, mapping, path);
+                    let item = std::mem::replace(&mut self.out, out);
+                    let text = format!("{}- {}", indent(level), &item[indent(level + 1).len()..]);
+                    self.key_line(level, path, text.trim_end_matches('\n'));
+                },
+                _ => {
+                    let prefix = format!("{}-", indent(level));
+                    let text = format!("{}{}", prefix, inline(value, level, Some(prefix.len())));
+                    self.key_line(level, path, &text);
+                },
+            }
+            path.pop();
+        }
+    }
+}
+
+/**
+ * A key as it's named in the path to a comment.
+ */
+fn key_name(key: &Value) -> String {
+    key.as_str().map(str::to_string).unwrap_or_else(|| scalar(key))
+}
+
+/**
+ * Whether a sequence is written in flow style, like `[hp, 1]`. Short sequences of scalars
+ * that aren't all strings are tuples, and read better that way.
+ */
+fn is_flow(sequence: &[Value]) -> bool {
+    sequence.iter().all(is_scalar) && !sequence.iter().all(|v| v.is_string())
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_))
+}
+
+/**
+ * A value written after `key:` or `-`, including the leading space. Long text is folded
+ * onto the following lines, unless `column` is `None` because the value is inside a flow
+ * sequence or tag.
+ */
+fn inline(value: &Value, level: usize, column: Option<usize>) -> String {
+    match value {
+        Value::String(text) if column.is_some_and(|c| c + 1 + scalar(value).len() > WIDTH) && is_foldable(text) => {
+            let content_indent = indent(level + 1);
+            let mut folded = " >-".to_string();
+            for line in wrap(text, WIDTH.saturating_sub(content_indent.len()).max(40)) {
+                folded.push('\n');
+                folded.push_str(&content_indent);
+                folded.push_str(&line);
+            }
+            folded
+        },
+        Value::String(text) if column.is_some() && text.contains('\n') => {
+            // Block scalars from serde_yaml are indented by two spaces.
+            let content_indent = indent(level + 1);
+            let block = scalar(value);
+            let mut lines = block.lines();
+            let mut out = format!(" {}", lines.next().unwrap_or_default());
+            for line in lines {
+                out.push('\n');
+                if !line.is_empty() {
+                    out.push_str(&content_indent);
+                    out.push_str(line.strip_prefix("  ").unwrap_or(line));
+                }
+            }
+            out
+        },
+        Value::Sequence(sequence) => {
+            let items: Vec<String> = sequence.iter().map(|v| inline(v, level, None).trim_start().to_string()).collect();
+            format!(" [{}]", items.join(", "))
+        },
+        Value::Mapping(_) => " {}".to_string(),
+        Value::Tagged(tagged) => format!(" {}{}", tagged.tag, inline(&tagged.value, level, None)),
+        _ => format!(" {}", scalar(value)),
+    }
+}
+
+/**
+ * A scalar as serde_yaml writes it, quoted if it needs to be.
+ */
+fn scalar(value: &Value) -> String {
+    serde_yaml::to_string(value).unwrap_or_default().trim_end_matches('\n').to_string()
+}
+
+/**
+ * Whether text can be folded without changing it: folding joins lines with single spaces,
+ * so the text must only have single spaces between words.
+ */
+fn is_foldable(text: &str) -> bool {
+    !text.is_empty()
+        && !text.contains(['\n', '\t'])
+        && !text.contains("  ")
+        && !text.starts_with(' ')
+        && !text.ends_with(' ')
+}
+
+/**
+ * Split text into lines of at most `width` characters, breaking between words.
+ */
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOKS: [&str; 3] = [
+        include_str!("../questbooks/5roomdungeon.questbook.yml"),
+        include_str!("../questbooks/minimal.questbook.yml"),
+        include_str!("../questbooks/steamedhams.questbook.yml"),
+    ];
+
+    const COMMENTED: &str = "# The story
+story:
+  title: Test  # working title
+  chapters: [start]
+chapters:
+  start:  # first chapter
+    title: Start
+    # Scenes in order
+    scenes:
+      - room  # the only room
+scenes:
+  room:
+    name: Room
+    background: |
+      A room.
+      # not a comment
+    decisions: [wait]
+    hints:
+      # gentle first
+      - text: \"Wait # really\"  # quoted hash
+      -
+        # Stronger
+        text: Just wait.
+items:
+decisions:
+  wait:
+    decision: Wait, it's 'fine'.  # apostrophes
+    consequences: [waited]
+characters:
+consequences:
+  waited:
+    conclusion: You wait.
+    counter: [hp, 1]
+statuses:
+counters:
+  hp:
+    name: HP
+    value: 1
+    visible: true
+triggers:
+# the end
+";
+
+    fn comments(text: &str) -> Vec<&str> {
+        text.lines().filter_map(|line| line.find(" # ").or(line.trim_start().starts_with('#').then_some(0)).map(|i| line[i..].trim())).collect()
+    }
+
+    #[test]
+    fn formatting_keeps_the_questbook_and_is_stable() {
+        for source in BOOKS {
+            let formatted = format_questbook(source).unwrap();
+            let before: Questbook = serde_yaml::from_str(source).unwrap();
+            let after: Questbook = serde_yaml::from_str(&formatted).unwrap();
+            assert_eq!(before, after);
+            assert_eq!(format_questbook(&formatted).unwrap(), formatted);
+            assert_eq!(comments(source).len(), comments(&formatted).len());
+        }
+    }
+
+    #[test]
+    fn comments_stay_where_they_are() {
+        let formatted = format_questbook(COMMENTED).unwrap();
+        assert!(formatted.starts_with("# The story\nstory:\n  title: Test  # working title\n"));
+        assert!(formatted.contains("\n  start:  # first chapter\n    title: Start\n    # Scenes in order\n    scenes:\n      - room  # the only room\n"));
+        assert!(formatted.contains("\n      A room.\n      # not a comment\n"));
+        assert!(formatted.contains("\n      # gentle first\n      - text: 'Wait # really'  # quoted hash\n      # Stronger\n      - text: Just wait.\n"));
+        assert!(formatted.contains("\n    decision: Wait, it's 'fine'.  # apostrophes\n"));
+        assert!(formatted.ends_with("\n# the end\n"));
+        assert_eq!(format_questbook(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn comments_that_cant_be_kept_stop_formatting() {
+        let inside_tuple = COMMENTED.replace("    counter: [hp, 1]\n", "    counter:\n      - hp  # which\n      - 1\n");
+        let error = format_questbook(&inside_tuple).unwrap_err();
+        assert_eq!(error, "Comment on line 34 can't be kept when formatting: # which");
+        let inside_flow = COMMENTED.replace("  chapters: [start]\n", "  chapters: [\n    start,  # first\n  ]\n");
+        assert!(format_questbook(&inside_flow).is_err());
+    }
+
+    fn scene(name: &str) -> Scene {
+        Scene {
+            name: name.to_string(),
+            background: "Somewhere.".to_string(),
+            exposition: None,
+            characters: None,
+            decisions: Vec::new(),
+            requirements: None,
+            on_enter: None,
+            on_exit: None,
+            hints: None,
+        }
+    }
+
+    fn path(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn inserting_an_entry_leaves_the_rest_alone() {
+        let mut questbook: Questbook = serde_yaml::from_str(COMMENTED).unwrap();
+        questbook.chapters.get_mut("start").unwrap().scenes.push("hall".to_string());
+        questbook.scenes.insert("hall".to_string(), scene("Hall"));
+        let list = path(&["chapters", "start", "scenes"]);
+        let added = insert_entry(COMMENTED, &questbook, "scenes", "hall", Some(&list)).unwrap();
+        let expected = COMMENTED
+            .replace("      - room  # the only room\n", "      - room  # the only room\n      - hall\n")
+            .replace("        text: Just wait.\n", "        text: Just wait.\n  hall:\n    name: Hall\n    background: Somewhere.\n    decisions: []\n");
+        assert_eq!(added, expected);
+    }
+
+    #[test]
+    fn inserting_rewrites_only_the_list_it_adds_to() {
+        let mut questbook: Questbook = serde_yaml::from_str(COMMENTED).unwrap();
+        questbook.scenes.get_mut("room").unwrap().decisions.push("hall".to_string());
+        questbook.story.decisions = Some(vec!["hall".to_string()]);
+        questbook.scenes.insert("hall".to_string(), scene("Hall"));
+        let list = path(&["scenes", "room", "decisions"]);
+        let added = insert_entry(COMMENTED, &questbook, "scenes", "hall", Some(&list));
+        // Only one list can be added to.
+        assert!(added.is_err());
+
+        questbook.scenes.get_mut("room").unwrap().decisions.pop();
+        let list = path(&["story", "decisions"]);
+        let added = insert_entry(COMMENTED, &questbook, "scenes", "hall", Some(&list)).unwrap();
+        assert!(added.starts_with("# The story\nstory:\n  title: Test  # working title\n  chapters: [start]\n  decisions:\n    - hall\nchapters:\n"));
+
+        let mut questbook: Questbook = serde_yaml::from_str(COMMENTED).unwrap();
+        questbook.scenes.get_mut("room").unwrap().decisions.push("hall".to_string());
+        questbook.scenes.insert("hall".to_string(), scene("Hall"));
+        let list = path(&["scenes", "room", "decisions"]);
+        let added = insert_entry(COMMENTED, &questbook, "scenes", "hall", Some(&list)).unwrap();
+        assert!(added.contains("\n      # not a comment\n    decisions:\n      - wait\n      - hall\n    hints:\n"));
+    }
+
+}
@@ -0,0 +1,68 @@
+use crate::book::*;
+
+/**
+ * Render the scenes of a questbook as a Graphviz DOT graph. Scenes are grouped by chapter,
+ * and each decision that moves the player to another scene is an edge.
+ */
+pub fn to_dot(questbook: &Questbook) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", escape(questbook.title()));
+    dot.push_str("    node [shape=box];\n");
+
+    for (index, chapter_id) in questbook.story.chapters.iter().enumerate() {
+        let chapter = match questbook.chapters.get(chapter_id) {
+            Some(chapter) => chapter,
+            None => continue,
+        };
+        dot.push_str(&format!("    subgraph cluster_{} {{\n", index));
+        dot.push_str(&format!("        label=\"{}\";\n", escape(&chapter.title)));
+        for scene_id in chapter.scenes.iter() {
+            let name = questbook.scenes.get(scene_id).map(|s| s.name.as_str()).unwrap_or(scene_id);
+            dot.push_str(&format!("        \"{}\" [label=\"{}\"];\n", escape(scene_id), escape(name)));
+        }
+        dot.push_str("    }\n");
+    }
+
+    let mut scenes: Vec<(&String, &Scene)> = questbook.scenes.iter().collect();
+    scenes.sort_by_key(|(id, _)| *id);
+    for (scene_id, scene) in scenes {
+        for decision_id in scene.decisions.iter() {
+            for target in scene_targets(questbook, decision_id) {
+                dot.push_str(&format!("    \"{}\" -> \"{}\" [label=\"{}\"];\n", escape(scene_id), escape(&target), escape(decision_id)));
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/**
+ * IDs of the scenes a decision can move the player to.
+ */
+pub fn scene_targets(questbook: &Questbook, decision: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let decision = match questbook.decisions.get(decision) {
+        Some(decision) => decision,
+        None => return targets,
+    };
+    let mut consequences: Vec<&String> = decision.consequences.iter().collect();
+    consequences.extend(decision.outcomes.iter().flatten().flat_map(|o| o.consequences.iter()));
+    if let Some(check) = &decision.check {
+        consequences.extend(check.success.iter().chain(check.failure.iter()));
+    }
+    if let Some(prompt) = &decision.prompt {
+        consequences.extend(prompt.success.iter().chain(prompt.failure.iter()));
+    }
+    for consequence in consequences {
+        if let Some(scene) = questbook.consequences.get(consequence).and_then(|c| c.scene.as_ref()) {
+            if !targets.contains(scene) {
+                targets.push(scene.clone());
+            }
+        }
+    }
+    targets
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
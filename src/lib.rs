@@ -1,3 +1,15 @@
 pub mod adventure;
+pub mod authoring;
 pub mod book;
-pub mod textrunner;
\ No newline at end of file
+pub mod builder;
+pub mod cli;
+pub mod diff;
+pub mod format;
+pub mod graph;
+pub mod playtest;
+pub mod profile;
+pub mod random;
+pub mod save;
+pub mod stats;
+pub mod textrunner;
+pub mod validator;
\ No newline at end of file
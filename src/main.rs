@@ -1,14 +1,8 @@
 use std::env;
-use questbook::book::load_questbook;
-use questbook::adventure::Adventure;
-use questbook::textrunner::TextRunner;
+use std::process;
+use questbook::cli::run;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let file = &args[1];
-    let dev_mode = args.len() > 2 && args[2] == "dev";
-    let questbook = load_questbook(file);
-    let adventure = Adventure::new(questbook);
-    let mut textrunner = TextRunner::new(adventure, dev_mode);
-    textrunner.start();
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(run(&args));
 }
@@ -0,0 +1,268 @@
+use crate::adventure::*;
+
+/**
+ * Play through an adventure with a script, returning a description of each failure.
+ *
+ * Each line of the script is a command as the player would type it, or an expectation
+ * about the state of the adventure:
+ *
+ * ```text
+ * # Comments and blank lines are ignored.
+ * take_sword
+ * expect has rusty_sword
+ * cross_swamp
+ * expect scene evil_doorway
+ * expect counter hp 100
+ * expect output murky waters
+ * ```
+ *
+ * Decisions that ask a question are followed by the answer, like `knock_door bull`.
+ *
+ * Character creation comes before any other line, with `create name <name>`,
+ * `create <choice> <option>` and `create <counter> <points>`.
+ *
+ * The script stops at the first command that can't be carried out.
+ */
+pub fn run_script(adventure: &mut Adventure, script: &str) -> Vec<String> {
+    let mut failures = Vec::new();
+    let mut character: Option<PlayerCharacter> = None;
+    let mut output: Option<Vec<String>> = None;
+
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let number = index + 1;
+
+        if let Some(creation) = line.strip_prefix("create ") {
+            if output.is_some() {
+                failures.push(format!("line {}: characters are created before the adventure starts", number));
+                break;
+            }
+            if let Err(failure) = add_to_character(adventure, character.get_or_insert_with(PlayerCharacter::default), creation.trim()) {
+                failures.push(format!("line {}: {}", number, failure));
+                break;
+            }
+            continue;
+        }
+        if output.is_none() {
+            match start(adventure, character.take()) {
+                Ok(start_output) => output = Some(start_output),
+                Err(error) => {
+                    failures.push(format!("line {}: {}", number, error));
+                    break;
+                },
+            }
+        }
+        let output = output.as_mut().unwrap();
+
+        if let Some(expectation) = line.strip_prefix("expect ") {
+            if let Err(failure) = check_expectation(adventure, output, expectation.trim()) {
+                failures.push(format!("line {}: {}", number, failure));
+            }
+            continue;
+        }
+
+        match run_command(adventure, line) {
+            Some(result) => *output = result,
+            None => {
+                failures.push(format!("line {}: can't {}", number, line));
+                break;
+            },
+        }
+    }
+    if output.is_none() && failures.is_empty() {
+        if let Err(error) = start(adventure, character) {
+            failures.push(error);
+        }
+    }
+    failures
+}
+
+/**
+ * Create the character, if there's one, and start the adventure, returning its output.
+ */
+fn start(adventure: &mut Adventure, character: Option<PlayerCharacter>) -> Result<Vec<String>, String> {
+    if let Some(character) = character {
+        adventure.create_character(character)?;
+    }
+    Ok(adventure.start())
+}
+
+/**
+ * Add a `create` line to the character: its name, the option picked for a choice, or the
+ * points put into a counter.
+ */
+fn add_to_character(adventure: &Adventure, character: &mut PlayerCharacter, creation: &str) -> Result<(), String> {
+    let (id, value) = creation.split_once(' ').ok_or(format!("invalid creation: {}", creation))?;
+    let value = value.trim();
+    let mut choices = adventure.questbook.story.creation.iter().flat_map(|c| c.choices.iter().flatten());
+    if id == "name" {
+        character.name = Some(value.to_string());
+    } else if choices.any(|choice| choice.id == id) {
+        character.choices.insert(id.to_string(), value.to_string());
+    } else {
+        let points = value.parse().map_err(|_| format!("invalid points: {}", value))?;
+        character.points.insert(id.to_string(), points);
+    }
+    Ok(())
+}
+
+/**
+ * Carry out a player command, returning its output, or `None` if it isn't possible.
+ */
+fn run_command(adventure: &mut Adventure, command: &str) -> Option<Vec<String>> {
+    if let Some(item) = command.strip_prefix("use ") {
+        return adventure.use_item(item.trim());
+    }
+    if command == "hint" {
+        return adventure.hint();
+    }
+    if let Some(item) = command.strip_prefix("drop ") {
+        return adventure.drop_item(item.trim()).then(Vec::new);
+    }
+    if let Some(items) = command.strip_prefix("combine ") {
+        let items: Vec<&str> = items.split_whitespace().collect();
+        return match items[..] {
+            [first, second] => adventure.combine_items(first, second),
+            _ => None,
+        };
+    }
+    if adventure.get_scenes().contains(command) {
+        return Some(adventure.change_scene(command, true));
+    }
+    if adventure.get_decisions().contains(command) {
+        return Some(adventure.make_decision(command));
+    }
+    // `<decision> <answer>` for decisions that ask a question.
+    if let Some((decision, answer)) = command.split_once(' ') {
+        if adventure.get_decisions().contains(decision) && adventure.prompt(decision).is_some() {
+            return Some(adventure.answer(decision, answer));
+        }
+    }
+    None
+}
+
+fn check_expectation(adventure: &Adventure, output: &[String], expectation: &str) -> Result<(), String> {
+    let (kind, args) = expectation.split_once(' ').unwrap_or((expectation, ""));
+    let args = args.trim();
+    let passed = match kind {
+        "chapter" => adventure.chapter.as_deref() == Some(args),
+        "scene" => adventure.scene.as_deref() == Some(args),
+        "has" => adventure.inventory.contains_key(args) || adventure.statuses.contains(args) || adventure.tags.contains(args),
+        "lacks" => !(adventure.inventory.contains_key(args) || adventure.statuses.contains(args) || adventure.tags.contains(args)),
+        "decision" => adventure.get_decisions().contains(args),
+        "ended" => matches!(adventure.state, AdventureState::Ended(_)),
+        "ending" => adventure.ending().map(|e| e.as_str()) == Some(args),
+        "turn" => args.parse() == Ok(adventure.turn),
+        "output" => output.iter().any(|line| line.to_lowercase().contains(&args.to_lowercase())),
+        "counter" => match args.split_once(' ') {
+            Some((counter, value)) => value.trim().parse().ok() == adventure.counters.get(counter).copied(),
+            None => return Err(format!("invalid expectation: {}", expectation)),
+        },
+        "variable" => match args.split_once(' ') {
+            Some((variable, value)) => adventure.variables.get(variable).map(|v| v.as_str()) == Some(value.trim()),
+            None => return Err(format!("invalid expectation: {}", expectation)),
+        },
+        _ => return Err(format!("invalid expectation: {}", expectation)),
+    };
+    if passed {
+        Ok(())
+    } else {
+        Err(format!("expected {}", expectation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "
+story:
+  title: Test
+  chapters: [start]
+chapters:
+  start:
+    title: Start
+    scenes: [room]
+scenes:
+  room:
+    name: Room
+    background: A room.
+    decisions: [take, wait]
+items:
+  sword:
+    name: Sword
+    description: A sword.
+decisions:
+  take:
+    decision: Take the sword.
+    consequences: [took]
+  wait:
+    decision: Wait.
+    consequences: [waited]
+characters:
+consequences:
+  took:
+    conclusion: You take the sword.
+    provides: [sword]
+    counter: [gold, -1]
+  waited:
+    conclusion: You wait.
+statuses:
+counters:
+  gold:
+    name: Gold
+    value: 5
+    visible: true
+triggers:
+";
+
+    fn run(script: &str) -> Vec<String> {
+        let mut adventure = Adventure::with_seed(serde_yaml::from_str(BOOK).unwrap(), 1);
+        run_script(&mut adventure, script)
+    }
+
+    #[test]
+    fn met_expectations_pass() {
+        let script = "
+# Comments and blank lines are ignored.
+expect scene room
+expect lacks sword
+take
+expect has sword
+expect counter gold 4
+expect output take the SWORD
+expect turn 1
+expect decision wait
+";
+        assert_eq!(run(script), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unmet_expectations_fail_with_their_line() {
+        let script = "take\nexpect lacks sword\nexpect counter gold 5\nexpect output you wait";
+        assert_eq!(run(script), vec![
+            "line 2: expected lacks sword",
+            "line 3: expected counter gold 5",
+            "line 4: expected output you wait",
+        ]);
+    }
+
+    #[test]
+    fn malformed_expectations_are_invalid() {
+        let script = "expect counter gold\nexpect variable name\nexpect weather sunny";
+        assert_eq!(run(script), vec![
+            "line 1: invalid expectation: counter gold",
+            "line 2: invalid expectation: variable name",
+            "line 3: invalid expectation: weather sunny",
+        ]);
+    }
+
+    #[test]
+    fn scripts_stop_at_a_command_that_cant_be_carried_out() {
+        let script = "fly\nexpect turn 5";
+        assert_eq!(run(script), vec!["line 1: can't fly"]);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::adventure::Adventure;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+/// What the player has done across every playthrough of a questbook.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// IDs of the endings reached, in the order they were first reached.
+    #[serde(default)]
+    pub endings: Vec<String>,
+    /// IDs of the achievements earned.
+    #[serde(default)]
+    pub achievements: Vec<String>,
+    /// IDs of the tags given at the start of every new game.
+    #[serde(default)]
+    pub unlocks: Vec<String>,
+}
+
+impl Profile {
+    /**
+     * Record reaching an ending, returning false if it was reached before.
+     */
+    pub fn add_ending(&mut self, ending: &str) -> bool {
+        add(&mut self.endings, ending)
+    }
+
+    /**
+     * Record what was achieved in an adventure: its ending, the achievements earned and
+     * what they unlock. Returns false if there was nothing new.
+     */
+    pub fn record(&mut self, adventure: &Adventure) -> bool {
+        let mut changed = false;
+        if let Some(ending) = adventure.ending() {
+            changed |= self.add_ending(ending);
+        }
+        let mut earned: Vec<&String> = adventure.achievements.iter().collect();
+        earned.sort();
+        for id in earned {
+            if !add(&mut self.achievements, id) {
+                continue;
+            }
+            changed = true;
+            let unlocks = adventure.questbook.achievements.get(id).and_then(|a| a.unlocks.as_ref());
+            for tag in unlocks.into_iter().flatten() {
+                add(&mut self.unlocks, tag);
+            }
+        }
+        changed
+    }
+}
+
+/**
+ * Add an ID to a list if it isn't there already, returning false if it was.
+ */
+fn add(ids: &mut Vec<String>, id: &str) -> bool {
+    if ids.iter().any(|i| i == id) {
+        return false;
+    }
+    ids.push(id.to_string());
+    true
+}
+
+/**
+ * Path of the profile for the questbook with the given title.
+ */
+pub fn profile_path(save_dir: &str, title: &str) -> PathBuf {
+    let name: String = title.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    Path::new(save_dir).join(format!("{}.profile.yml", name))
+}
+
+/**
+ * Read the profile for a questbook, or an empty profile if there isn't one yet.
+ */
+pub fn read_profile(save_dir: &str, title: &str) -> Result<Profile, String> {
+    let path = profile_path(save_dir, title);
+    if !path.exists() {
+        return Ok(Profile::default());
+    }
+    let file = File::open(&path).map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+    serde_yaml::from_reader(file).map_err(|e| format!("Can't read {}: {}", path.display(), e))
+}
+
+pub fn write_profile(save_dir: &str, title: &str, profile: &Profile) -> Result<PathBuf, String> {
+    fs::create_dir_all(save_dir).map_err(|e| format!("Can't create {}: {}", save_dir, e))?;
+    let path = profile_path(save_dir, title);
+    let file = File::create(&path).map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+    serde_yaml::to_writer(file, profile).map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+    Ok(path)
+}
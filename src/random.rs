@@ -0,0 +1,258 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+/// Seedable pseudo-random number generator (SplitMix64).
+///
+/// The whole state is a single `u64`, so it can be stored alongside the rest of the
+/// adventure state and a playthrough can be reproduced from the same seed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rng {
+    pub seed: u64,
+    pub state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            seed,
+            state: seed,
+        }
+    }
+
+    /// Seed a generator from the system clock.
+    pub fn from_time() -> Rng {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Rng::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    /**
+     * Returns a number in `0..max`. Returns 0 if `max` is 0.
+     */
+    pub fn below(&mut self, max: u64) -> u64 {
+        if max == 0 {
+            return 0;
+        }
+        self.next_u64() % max
+    }
+
+    /**
+     * Returns a number in `low..=high`.
+     */
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+        low + self.below((high - low) as u64 + 1) as i32
+    }
+
+    /**
+     * Returns a number in `1..=100` derived from the current state and `salt`, without
+     * advancing the generator.
+     */
+    pub fn peek_percent(&self, salt: &str) -> u32 {
+        let mut hash = self.state;
+        for byte in salt.bytes() {
+            hash = mix(hash ^ byte as u64);
+        }
+        (mix(hash) % 100) as u32 + 1
+    }
+
+    /**
+     * Pick an index from a list of weights. Returns `None` if all weights are 0.
+     */
+    pub fn weighted(&mut self, weights: &[u32]) -> Option<usize> {
+        let total: u64 = weights.iter().map(|w| *w as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.below(total);
+        for (index, weight) in weights.iter().enumerate() {
+            if roll < *weight as u64 {
+                return Some(index);
+            }
+            roll -= *weight as u64;
+        }
+        None
+    }
+
+    /**
+     * Roll dice and return the individual results.
+     */
+    pub fn roll(&mut self, dice: &Dice) -> Vec<i32> {
+        (0..dice.count).map(|_| self.range(1, dice.sides as i32)).collect()
+    }
+}
+
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Most dice that can be rolled at once.
+pub const MAX_DICE: u32 = 100;
+/// Most sides a die can have.
+pub const MAX_SIDES: u32 = 1000;
+/// Largest modifier that can be added to or subtracted from a roll.
+pub const MAX_MODIFIER: i32 = 10000;
+
+/// Dice in the usual notation, e.g. `1d6`, `2d10+3` or `d20-1`.
+/// A leading `-` (e.g. `-1d6`) subtracts the roll instead of adding it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Dice {
+    pub negative: bool,
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: i32,
+}
+
+impl Dice {
+    /**
+     * Total of a roll of these dice, including the sign and modifier.
+     */
+    pub fn total(&self, rolls: &[i32]) -> i32 {
+        let sum: i32 = rolls.iter().sum();
+        if self.negative {
+            self.modifier - sum
+        } else {
+            sum + self.modifier
+        }
+    }
+}
+
+impl TryFrom<String> for Dice {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid dice: {}", value);
+        let text = value.trim().to_ascii_lowercase();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text.to_string()),
+            None => (false, text),
+        };
+        let (count, rest) = text.split_once('d').ok_or_else(invalid)?;
+        let count = if count.is_empty() { 1 } else { count.parse().map_err(|_| invalid())? };
+        let (sides, modifier): (&str, i32) = match rest.find(['+', '-']) {
+            Some(index) => (&rest[..index], rest[index..].trim_start_matches('+').parse().map_err(|_| invalid())?),
+            None => (rest, 0),
+        };
+        let sides = sides.parse().map_err(|_| invalid())?;
+        if count == 0 || sides == 0 {
+            return Err(invalid());
+        }
+        // Keep the total of any roll well within an `i32`.
+        if count > MAX_DICE || sides > MAX_SIDES || modifier.unsigned_abs() > MAX_MODIFIER as u32 {
+            return Err(format!("Invalid dice: {}, at most {}d{} with a modifier up to {}", value, MAX_DICE, MAX_SIDES, MAX_MODIFIER));
+        }
+        Ok(Dice { negative, count, sides, modifier })
+    }
+}
+
+impl From<Dice> for String {
+    fn from(dice: Dice) -> Self {
+        dice.to_string()
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.modifier {
+            0 => Ok(()),
+            m if m > 0 => write!(f, "+{}", m),
+            m => write!(f, "{}", m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dice(text: &str) -> Result<Dice, String> {
+        Dice::try_from(text.to_string())
+    }
+
+    #[test]
+    fn parses_dice() {
+        assert_eq!(dice("1d6"), Ok(Dice { negative: false, count: 1, sides: 6, modifier: 0 }));
+        assert_eq!(dice("2d10+3"), Ok(Dice { negative: false, count: 2, sides: 10, modifier: 3 }));
+        assert_eq!(dice("d20-1"), Ok(Dice { negative: false, count: 1, sides: 20, modifier: -1 }));
+        assert_eq!(dice(" -1D6 "), Ok(Dice { negative: true, count: 1, sides: 6, modifier: 0 }));
+    }
+
+    #[test]
+    fn rejects_invalid_dice() {
+        for text in ["", "6", "d", "0d6", "1d0", "1d6+", "1d6+x", "xd6", "1d-6"] {
+            assert!(dice(text).is_err(), "{} should be invalid", text);
+        }
+    }
+
+    #[test]
+    fn rejects_dice_that_could_overflow() {
+        assert!(dice("100d1000+10000").is_ok());
+        assert!(dice("101d6").is_err());
+        assert!(dice("1d1001").is_err());
+        assert!(dice("1d6+10001").is_err());
+        assert!(dice("1d6-2147483648").is_err());
+        assert!(dice("4294967295d4294967295").is_err());
+    }
+
+    #[test]
+    fn dice_round_trip_through_text() {
+        for text in ["1d6", "2d10+3", "1d20-1", "-1d6", "-3d4+2"] {
+            assert_eq!(dice(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn rolls_stay_in_bounds() {
+        let mut rng = Rng::new(7);
+        let dice = dice("3d6+2").unwrap();
+        for _ in 0..1000 {
+            let rolls = rng.roll(&dice);
+            assert_eq!(rolls.len(), 3);
+            assert!(rolls.iter().all(|roll| (1..=6).contains(roll)));
+            assert!((5..=20).contains(&dice.total(&rolls)));
+        }
+    }
+
+    #[test]
+    fn negative_dice_subtract() {
+        let dice = dice("-2d6+1").unwrap();
+        assert_eq!(dice.total(&[3, 4]), -6);
+    }
+
+    #[test]
+    fn same_seed_gives_same_rolls() {
+        let dice = dice("1d100").unwrap();
+        let mut first = Rng::new(42);
+        let mut second = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(first.roll(&dice), second.roll(&dice));
+        }
+    }
+
+    #[test]
+    fn weighted_skips_zero_weights() {
+        let mut rng = Rng::new(3);
+        assert_eq!(rng.weighted(&[0, 0]), None);
+        for _ in 0..100 {
+            assert_eq!(rng.weighted(&[0, 5, 0]), Some(1));
+        }
+    }
+}
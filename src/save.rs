@@ -0,0 +1,83 @@
+use crate::adventure::{Adventure, JournalEntry, PlayerCharacter, QuestState};
+use crate::random::Rng;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+/// The state of an adventure, as stored in a save file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    /// Title of the questbook the game was saved from.
+    pub title: String,
+    pub tags: HashSet<String>,
+    pub inventory: HashMap<String, u32>,
+    pub statuses: HashSet<String>,
+    pub chapter: Option<String>,
+    pub scene: Option<String>,
+    pub counters: HashMap<String, i32>,
+    pub log: Vec<String>,
+    pub rng: Rng,
+    pub turn: u32,
+    pub scheduled: Vec<(u32, String)>,
+    pub status_expiry: HashMap<String, u32>,
+    /// IDs of the consequences that only apply once, and have been applied.
+    #[serde(default)]
+    pub applied_once: HashSet<String>,
+    #[serde(default)]
+    pub visited_chapters: Vec<String>,
+    #[serde(default)]
+    pub furthest_chapter: Option<String>,
+    #[serde(default)]
+    pub decisions_made: Vec<String>,
+    /// ID of the ending reached, if the adventure is over.
+    #[serde(default)]
+    pub ending: Option<String>,
+    #[serde(default)]
+    pub achievements: HashSet<String>,
+    #[serde(default)]
+    pub journal: Vec<JournalEntry>,
+    #[serde(default)]
+    pub quests: HashMap<String, QuestState>,
+    #[serde(default)]
+    pub scene_hints: HashMap<String, Vec<usize>>,
+    #[serde(default)]
+    pub chapter_hints: HashMap<String, Vec<usize>>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    #[serde(default)]
+    pub character: Option<PlayerCharacter>,
+}
+
+/**
+ * Path of the save file with the given name.
+ */
+pub fn save_path(save_dir: &str, name: &str) -> PathBuf {
+    Path::new(save_dir).join(format!("{}.save.yml", name))
+}
+
+/**
+ * Make sure a save name can't point outside the save directory.
+ */
+fn check_name(name: &str) -> Result<(), String> {
+    if name.contains(['/', '\\']) || name.contains("..") {
+        return Err(format!("Invalid save name {}, it can't contain '/', '\\' or '..'", name));
+    }
+    Ok(())
+}
+
+pub fn write_save(save_dir: &str, name: &str, adventure: &Adventure) -> Result<PathBuf, String> {
+    check_name(name)?;
+    fs::create_dir_all(save_dir).map_err(|e| format!("Can't create {}: {}", save_dir, e))?;
+    let path = save_path(save_dir, name);
+    let file = File::create(&path).map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+    serde_yaml::to_writer(file, &adventure.save()).map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn read_save(save_dir: &str, name: &str) -> Result<SaveGame, String> {
+    check_name(name)?;
+    let path = save_path(save_dir, name);
+    let file = File::open(&path).map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+    serde_yaml::from_reader(file).map_err(|e| format!("Can't read {}: {}", path.display(), e))
+}
@@ -0,0 +1,338 @@
+use crate::book::*;
+use std::collections::HashSet;
+use std::fmt;
+use serde::Serialize;
+
+/// Average reading speed used to estimate reading time, in words per minute.
+const WORDS_PER_MINUTE: f64 = 200.0;
+
+/// Size and shape of a questbook.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub title: String,
+    pub chapters: Vec<ChapterStats>,
+    /// Words in the whole questbook, counting each piece of text once.
+    pub words: usize,
+    /// Average number of decisions per scene.
+    pub branching_factor: f64,
+    pub reading_minutes: f64,
+    pub scenes: usize,
+    pub decisions: usize,
+    pub consequences: usize,
+    pub items: usize,
+    pub statuses: usize,
+    pub tags: usize,
+    pub counters: usize,
+    pub triggers: usize,
+    pub endings: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChapterStats {
+    pub id: String,
+    pub title: String,
+    pub words: usize,
+    pub scenes: Vec<SceneStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SceneStats {
+    pub id: String,
+    pub name: String,
+    pub background_words: usize,
+    pub exposition_words: usize,
+    /// Words in the hints for the scene.
+    pub hint_words: usize,
+    /// Words in the decisions of the scene.
+    pub decision_words: usize,
+    /// Words in the consequences of the decisions of the scene.
+    pub consequence_words: usize,
+    pub words: usize,
+    pub decisions: usize,
+}
+
+pub fn stats(questbook: &Questbook) -> Stats {
+    let mut chapters = Vec::new();
+    for chapter_id in questbook.story.chapters.iter() {
+        let chapter = match questbook.chapters.get(chapter_id) {
+            Some(chapter) => chapter,
+            None => continue,
+        };
+        let scenes: Vec<SceneStats> = chapter.scenes.iter()
+            .filter_map(|id| questbook.scenes.get(id).map(|scene| scene_stats(questbook, id, scene)))
+            .collect();
+        chapters.push(ChapterStats {
+            id: chapter_id.clone(),
+            title: chapter.title.clone(),
+            words: chapter_text(chapter) + scenes.iter().map(|s| s.words).sum::<usize>(),
+            scenes,
+        });
+    }
+
+    let scene_count = chapters.iter().map(|c| c.scenes.len()).sum::<usize>();
+    let decision_count = chapters.iter().flat_map(|c| c.scenes.iter()).map(|s| s.decisions).sum::<usize>();
+    let branching_factor = if scene_count == 0 { 0.0 } else { decision_count as f64 / scene_count as f64 };
+
+    let total_words = book_words(questbook);
+    Stats {
+        title: questbook.title().clone(),
+        chapters,
+        words: total_words,
+        branching_factor,
+        reading_minutes: total_words as f64 / WORDS_PER_MINUTE,
+        scenes: questbook.scenes.len(),
+        decisions: questbook.decisions.len(),
+        consequences: questbook.consequences.len(),
+        items: questbook.items.len(),
+        statuses: questbook.statuses.len(),
+        tags: tags(questbook).len(),
+        counters: questbook.counters.len(),
+        triggers: questbook.triggers.len(),
+        endings: questbook.endings.len(),
+    }
+}
+
+fn scene_stats(questbook: &Questbook, id: &str, scene: &Scene) -> SceneStats {
+    let background_words = words(&scene.name) + words(&scene.background);
+    let exposition_words = scene.exposition.iter().flatten()
+        .flat_map(|e| e.text.iter())
+        .map(|t| words(&t.text))
+        .sum();
+    let hint_words = hints_text(scene.hints.as_deref());
+    let mut decision_words = 0;
+    let mut consequence_words = 0;
+    for decision in scene.decisions.iter().filter_map(|d| questbook.decisions.get(d)) {
+        decision_words += decision_text(decision);
+        consequence_words += decision_consequences(decision).iter()
+            .filter_map(|c| questbook.consequences.get(*c))
+            .map(consequence_text)
+            .sum::<usize>();
+    }
+    SceneStats {
+        id: id.to_string(),
+        name: scene.name.clone(),
+        background_words,
+        exposition_words,
+        hint_words,
+        decision_words,
+        consequence_words,
+        words: background_words + exposition_words + hint_words + decision_words + consequence_words,
+        decisions: scene.decisions.len(),
+    }
+}
+
+/**
+ * IDs of all the consequences a decision can lead to.
+ */
+fn decision_consequences(decision: &Decision) -> Vec<&String> {
+    let mut consequences: Vec<&String> = decision.consequences.iter().collect();
+    consequences.extend(decision.outcomes.iter().flatten().flat_map(|o| o.consequences.iter()));
+    if let Some(check) = &decision.check {
+        consequences.extend(check.success.iter().chain(check.failure.iter()));
+    }
+    if let Some(prompt) = &decision.prompt {
+        consequences.extend(prompt.success.iter().chain(prompt.failure.iter()));
+    }
+    consequences
+}
+
+fn chapter_text(chapter: &Chapter) -> usize {
+    words(&chapter.title) + chapter.intro.as_deref().map(words).unwrap_or(0) + hints_text(chapter.hints.as_deref())
+}
+
+fn hints_text(hints: Option<&[Hint]>) -> usize {
+    hints.into_iter().flatten().map(|h| words(&h.text)).sum()
+}
+
+fn decision_text(decision: &Decision) -> usize {
+    words(&decision.decision)
+        + decision.description.as_deref().map(words).unwrap_or(0)
+        + decision.locked_text.as_deref().map(words).unwrap_or(0)
+        + decision.prompt.as_ref().map(|p| words(&p.question)).unwrap_or(0)
+}
+
+fn consequence_text(consequence: &Consequence) -> usize {
+    words(&consequence.conclusion) + consequence.description.as_deref().map(words).unwrap_or(0)
+}
+
+/**
+ * Words in all of the text of a questbook the player can see.
+ */
+fn book_words(questbook: &Questbook) -> usize {
+    let mut total = words(questbook.title());
+    total += questbook.chapters.values().map(chapter_text).sum::<usize>();
+    for scene in questbook.scenes.values() {
+        total += words(&scene.name) + words(&scene.background) + hints_text(scene.hints.as_deref());
+        total += scene.exposition.iter().flatten().flat_map(|e| e.text.iter()).map(|t| words(&t.text)).sum::<usize>();
+    }
+    total += questbook.decisions.values().map(decision_text).sum::<usize>();
+    total += questbook.consequences.values().map(consequence_text).sum::<usize>();
+    total += questbook.items.values().map(|i| words(&i.name) + words(&i.description)).sum::<usize>();
+    total += questbook.statuses.values().map(|s| words(&s.description)).sum::<usize>();
+    total += questbook.endings.values().map(|e| words(&e.title) + words(&e.epilogue)).sum::<usize>();
+    total += questbook.achievements.values().map(|a| words(&a.title) + words(&a.description)).sum::<usize>();
+    total += questbook.quests.values().map(|q| words(&q.title) + words(&q.description)).sum::<usize>();
+    total += questbook.counters.values().filter(|c| c.visible).map(|c| words(&c.name)).sum::<usize>();
+    total += questbook.story.creation.as_ref().map(creation_text).unwrap_or(0);
+    total
+}
+
+fn creation_text(creation: &Creation) -> usize {
+    creation.choices.iter().flatten()
+        .map(|choice| {
+            words(&choice.question)
+                + choice.options.iter()
+                    .map(|o| words(&o.text) + o.description.as_deref().map(words).unwrap_or(0))
+                    .sum::<usize>()
+        })
+        .sum()
+}
+
+/**
+ * IDs provided by consequences that aren't items or statuses.
+ */
+fn tags(questbook: &Questbook) -> HashSet<&String> {
+    questbook.consequences.values()
+        .flat_map(|c| c.provides.iter().flatten())
+        .filter(|id| !questbook.items.contains_key(*id) && !questbook.statuses.contains_key(*id))
+        .collect()
+}
+
+fn words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}\n", self.title)?;
+        writeln!(f, "{:<32} {:>10} {:>10} {:>6} {:>10} {:>12} {:>7} {:>10}", "Words in", "Background", "Exposition", "Hints", "Decisions", "Consequences", "Total", "Choices")?;
+        for chapter in self.chapters.iter() {
+            writeln!(f, "{:<32} {:>10} {:>10} {:>6} {:>10} {:>12} {:>7} {:>10}", chapter.title, "", "", "", "", "", chapter.words, "")?;
+            for scene in chapter.scenes.iter() {
+                writeln!(
+                    f,
+                    "  {:<30} {:>10} {:>10} {:>6} {:>10} {:>12} {:>7} {:>10}",
+                    scene.name,
+                    scene.background_words,
+                    scene.exposition_words,
+                    scene.hint_words,
+                    scene.decision_words,
+                    scene.consequence_words,
+                    scene.words,
+                    scene.decisions,
+                )?;
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "Words:            {}", self.words)?;
+        writeln!(f, "Reading time:     {:.0} min", self.reading_minutes.ceil())?;
+        writeln!(f, "Branching factor: {:.2}", self.branching_factor)?;
+        writeln!(f, "Scenes:           {}", self.scenes)?;
+        writeln!(f, "Decisions:        {}", self.decisions)?;
+        writeln!(f, "Consequences:     {}", self.consequences)?;
+        writeln!(f, "Items:            {}", self.items)?;
+        writeln!(f, "Statuses:         {}", self.statuses)?;
+        writeln!(f, "Tags:             {}", self.tags)?;
+        writeln!(f, "Counters:         {}", self.counters)?;
+        writeln!(f, "Triggers:         {}", self.triggers)?;
+        writeln!(f, "Endings:          {}", self.endings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "
+story:
+  title: Test Book
+  chapters: [start]
+  creation:
+    choices:
+      - id: class
+        question: Who are you?
+        options:
+          - id: warrior
+            text: A warrior
+            description: Strong and brave.
+          - id: thief
+            text: A thief
+chapters:
+  start:
+    title: The Start
+    intro: It begins here.
+    scenes: [room]
+    hints:
+      - text: Look around.
+scenes:
+  room:
+    name: Small Room
+    background: A small dusty room.
+    decisions: [open]
+    hints:
+      - text: Try the door.
+items:
+  key:
+    name: Old Key
+    description: A rusty key.
+decisions:
+  open:
+    decision: Open the door.
+    description: A door.
+    locked_text: It is locked.
+    consequences: [opened]
+characters:
+consequences:
+  opened:
+    conclusion: The door opens.
+    description: Light pours in.
+    provides: [free]
+    ending: good
+statuses:
+  tired:
+    description: You are tired.
+counters:
+  gold:
+    name: Gold Coins
+    value: 0
+    visible: true
+  score:
+    name: Secret Score
+    value: 0
+    visible: false
+triggers:
+endings:
+  good:
+    title: Good End
+    epilogue: You escaped the room.
+";
+
+    #[test]
+    fn counts_the_words_of_each_scene_and_chapter() {
+        let stats = stats(&serde_yaml::from_str(BOOK).unwrap());
+        let chapter = &stats.chapters[0];
+        let scene = &chapter.scenes[0];
+        assert_eq!(scene.background_words, 6);
+        assert_eq!(scene.exposition_words, 0);
+        assert_eq!(scene.hint_words, 3);
+        assert_eq!(scene.decision_words, 8);
+        assert_eq!(scene.consequence_words, 6);
+        assert_eq!(scene.words, 23);
+        // The title, intro and hint of the chapter, and its scene.
+        assert_eq!(chapter.words, 30);
+    }
+
+    #[test]
+    fn counts_all_the_visible_text_once() {
+        let stats = stats(&serde_yaml::from_str(BOOK).unwrap());
+        // Everything in the scene and chapter, plus the title, items, statuses, endings,
+        // visible counters and character creation.
+        assert_eq!(stats.words, 30 + 2 + 5 + 3 + 6 + 2 + 10);
+        assert_eq!(stats.reading_minutes, 58.0 / WORDS_PER_MINUTE);
+        assert_eq!(stats.branching_factor, 1.0);
+        assert_eq!(
+            (stats.scenes, stats.decisions, stats.consequences, stats.items, stats.statuses, stats.tags, stats.counters, stats.triggers, stats.endings),
+            (1, 1, 1, 1, 1, 1, 2, 0, 1),
+        );
+    }
+}
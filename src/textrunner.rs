@@ -1,29 +1,204 @@
-use crate::{adventure::*, book::Describe};
-use std::io;
+use crate::adventure::*;
+use crate::book::{load_questbook, Achievement};
+use crate::profile::{read_profile, write_profile, Profile};
+use crate::save::{read_save, write_save};
+use crate::validator::validate;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+/// Number of journal entries recapped when a game is loaded.
+const RECAP_ENTRIES: usize = 5;
 
 pub struct TextRunner {
     dev_mode: bool,
     adventure: Adventure,
+    /// Questbook file to reload when it changes, and when it was last modified.
+    watching: Option<(String, Option<SystemTime>)>,
+    /// Directory save files are written to.
+    save_dir: String,
+    /// Chapter whose title was last shown to the player.
+    shown_chapter: Option<String>,
+    /// What the player has done in earlier playthroughs of the questbook.
+    profile: Profile,
+    out: Console,
+}
+
+/// Writes output for the player, keeping a copy in the transcript file if there is one.
+struct Console {
+    transcript: Option<File>,
+    /// The first thing that went wrong, like a save that couldn't be written. The game goes
+    /// on, but it's reported when the game is over.
+    error: Option<String>,
+}
+
+impl Console {
+    fn say(&mut self, text: &str) {
+        println!("{}", text);
+        self.record(text);
+    }
+
+    /**
+     * Write a line to the transcript. If that fails, the transcript is stopped.
+     */
+    fn record(&mut self, line: &str) {
+        let written = match &mut self.transcript {
+            Some(transcript) => writeln!(transcript, "{}", line),
+            None => return,
+        };
+        if let Err(error) = written {
+            self.transcript = None;
+            self.fail(format!("Can't write the transcript: {}", error));
+        }
+    }
+
+    /**
+     * Tell the player something went wrong, and remember it if it's the first thing.
+     */
+    fn fail(&mut self, error: String) {
+        self.say(&error);
+        self.error.get_or_insert(error);
+    }
+
+    fn say_all(&mut self, lines: &[String]) {
+        for line in lines {
+            self.say(line);
+        }
+        self.say("");
+    }
+
+    /**
+     * Read a command from the player. Returns `None` when there's no more input.
+     */
+    fn read(&mut self) -> Option<String> {
+        self.read_text().map(|command| command.to_ascii_lowercase())
+    }
+
+    /**
+     * Read a line of text from the player as they typed it. Returns `None` when there's no
+     * more input.
+     */
+    fn read_text(&mut self) -> Option<String> {
+        let mut text = String::new();
+        if io::stdin().read_line(&mut text).ok()? == 0 {
+            return None;
+        }
+        let text = text.trim().to_string();
+        self.record(&format!("> {}", text));
+        Some(text)
+    }
 }
 
 impl TextRunner {
     pub fn new(adventure: Adventure, dev_mode: bool) -> TextRunner {
         TextRunner{
-            dev_mode: dev_mode,
-            adventure: adventure,
+            dev_mode,
+            adventure,
+            watching: None,
+            save_dir: "saves".to_string(),
+            shown_chapter: None,
+            profile: Profile::default(),
+            out: Console {
+                transcript: None,
+                error: None,
+            },
         }
     }
 
-    pub fn start(&mut self) {
-        self.adventure.start();
+    /**
+     * Set the directory save files are written to.
+     */
+    pub fn save_dir(&mut self, save_dir: &str) {
+        self.save_dir = save_dir.to_string();
+    }
+
+    /**
+     * Write everything shown to the player, and everything they enter, to a file.
+     */
+    pub fn transcript(&mut self, path: &str) -> io::Result<()> {
+        self.out.transcript = Some(File::create(path)?);
+        Ok(())
+    }
 
-        println!("{}\n", self.adventure.questbook.story.title);
-        let chapter = self.adventure.chapter.as_ref().unwrap();
-        println!("~ {} ~\n", self.adventure.questbook.chapters.get(chapter).unwrap().title);
+    /**
+     * Reload the questbook from a file whenever it changes.
+     */
+    pub fn watch(&mut self, bookfile: &str) {
+        let modified = fs::metadata(bookfile).and_then(|m| m.modified()).ok();
+        self.watching = Some((bookfile.to_string(), modified));
+    }
+
+    /**
+     * Reload the watched questbook if the file changed since it was last loaded.
+     */
+    fn check_reload(&mut self, force: bool) {
+        let (bookfile, last_modified) = match &self.watching {
+            Some(watching) => watching.clone(),
+            None => return,
+        };
+        let modified = fs::metadata(&bookfile).and_then(|m| m.modified()).ok();
+        if !force && modified == last_modified {
+            return;
+        }
+        self.watching = Some((bookfile.clone(), modified));
+
+        self.out.say(&format!("DEV - Reloading {}", bookfile));
+        let questbook = match load_questbook(&bookfile) {
+            Ok(questbook) => questbook,
+            Err(error) => {
+                self.out.say(&format!("DEV - Reload failed, keeping the previous version: {}", error));
+                return;
+            },
+        };
+        let issues = validate(&questbook);
+        for issue in issues.iter() {
+            self.out.say(&format!("DEV - {}", issue));
+        }
+        if issues.iter().any(|i| i.is_error()) {
+            self.out.say("DEV - Reload failed, keeping the previous version");
+            return;
+        }
+        for change in self.adventure.rebind(questbook) {
+            self.out.say(&format!("DEV - {}", change));
+        }
+    }
+
+    /**
+     * Play the adventure until the player leaves or it ends. Returns the first thing that
+     * went wrong on the way, even though the player was able to keep playing.
+     */
+    pub fn start(&mut self) -> Result<(), String> {
+        self.play();
+        match self.out.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn play(&mut self) {
+        match read_profile(&self.save_dir, self.adventure.questbook.title()) {
+            Ok(profile) => self.profile = profile,
+            Err(error) => self.out.say(&error),
+        }
+        self.adventure.apply_profile(&self.profile);
+        self.out.say(&format!("{}\n", self.adventure.questbook.story.title));
+        if self.adventure.questbook.story.creation.is_some() && !self.create_character() {
+            self.out.say("Exiting game");
+            return;
+        }
+        let output = self.adventure.start();
+        if !output.is_empty() {
+            self.say_output(&output);
+        }
 
         loop {
-            if self.adventure.state == AdventureState::Ended {
-                println!("The End");
+            self.check_reload(false);
+            self.record_profile();
+            if self.adventure.chapter.is_some() && self.adventure.chapter != self.shown_chapter {
+                self.changed_chapter();
+            }
+            if matches!(self.adventure.state, AdventureState::Ended(_)) {
+                self.display_ending();
                 break;
             }
 
@@ -31,24 +206,34 @@ impl TextRunner {
             let cur_decisions = self.adventure.get_decisions();
 
             if self.dev_mode {
-                println!("DEV - Current chapter: {:#?}", self.adventure.chapter);
-                println!("DEV - Current scene: {:#?}", self.adventure.scene);
-                println!("DEV - Current inventory: {:#?}", self.adventure.inventory);
-                println!("DEV - Current statuses: {:#?}", self.adventure.statuses);
-                println!("DEV - Available scenes: {:#?}", cur_scenes);
-                println!("DEV - Available decisions: {:#?}", cur_decisions);
+                self.out.say(&format!("DEV - Current chapter: {:#?}", self.adventure.chapter));
+                self.out.say(&format!("DEV - Visited chapters: {:?}", self.adventure.visited_chapters));
+                self.out.say(&format!("DEV - Current scene: {:#?}", self.adventure.scene));
+                self.out.say(&format!("DEV - Current inventory: {:#?}", self.adventure.inventory));
+                self.out.say(&format!("DEV - Current statuses: {:#?}", self.adventure.statuses));
+                self.out.say(&format!("DEV - Current tags: {:#?}", self.adventure.tags));
+                self.out.say(&format!("DEV - Current counters: {:#?}", self.adventure.counters));
+                self.out.say(&format!("DEV - Current variables: {:#?}", self.adventure.variables));
+                self.out.say(&format!("DEV - Current turn: {}", self.adventure.turn));
+                self.out.say(&format!("DEV - Scheduled consequences: {:#?}", self.adventure.scheduled));
+                self.out.say(&format!("DEV - Available scenes: {:#?}", cur_scenes));
+                self.out.say(&format!("DEV - Available decisions: {:#?}", cur_decisions));
             }
 
             self.display_scene();
 
             self.display_decisions();
 
-            println!("Enter your action: ");
-            let mut command = String::new();
-            io::stdin().read_line(&mut command).unwrap();
-            command = command.trim().to_ascii_lowercase();
+            self.out.say("Enter your action: ");
+            let command = match self.out.read() {
+                Some(command) => command,
+                None => {
+                    self.out.say("Exiting game");
+                    break;
+                },
+            };
             if command == "exit" || command == "quit" {
-                println!("Exiting game");
+                self.out.say("Exiting game");
                 break;
             }
             if command == "help" {
@@ -59,31 +244,186 @@ impl TextRunner {
                 self.display_inventory();
                 continue;
             }
+            if command == "journal" {
+                self.display_journal();
+                continue;
+            }
+            if command == "achievements" {
+                self.display_achievements();
+                continue;
+            }
+            if command == "quests" {
+                self.display_quests();
+                continue;
+            }
+            if command == "hint" {
+                match self.adventure.hint() {
+                    Some(output) => self.say_output(&output),
+                    None => self.out.say("There are no more hints here."),
+                }
+                continue;
+            }
             if command == "look" {
                 self.display_scenes();
                 continue;
             }
+            if command == "save" || command.starts_with("save ") {
+                self.save_game(command.strip_prefix("save").unwrap().trim());
+                continue;
+            }
+            if command == "load" || command.starts_with("load ") {
+                self.load_game(command.strip_prefix("load").unwrap().trim());
+                continue;
+            }
+            if self.dev_mode && self.dev_command(&command) {
+                continue;
+            }
+            if let Some(item) = command.strip_prefix("use ") {
+                match self.adventure.use_item(item.trim()) {
+                    Some(output) => self.say_output(&output),
+                    None => self.out.say("You can't use that."),
+                }
+                continue;
+            }
+            if let Some(item) = command.strip_prefix("drop ") {
+                if !self.adventure.drop_item(item.trim()) {
+                    self.out.say("You can't drop that.");
+                }
+                continue;
+            }
+            if let Some(items) = command.strip_prefix("combine ") {
+                let items: Vec<&str> = items.split_whitespace().collect();
+                match items[..] {
+                    [first, second] => match self.adventure.combine_items(first, second) {
+                        Some(output) => self.say_output(&output),
+                        None => self.out.say("Those don't go together."),
+                    },
+                    _ => self.out.say("Combine two items: combine <item> <item>"),
+                }
+                continue;
+            }
 
             if cur_scenes.contains(&command) {
-                self.adventure.change_scene(&command, true);
+                let output = self.adventure.change_scene(&command, true);
+                if !output.is_empty() {
+                    self.say_output(&output);
+                }
             } else if cur_decisions.contains(&command) {
-                let output = self.adventure.make_decision(&command);
-                println!("{:#?}", output);
+                let output = match self.adventure.prompt(&command).cloned() {
+                    Some(question) => {
+                        self.out.say(&self.adventure.fill_template(&question));
+                        let answer = self.out.read_text().unwrap_or_default();
+                        self.adventure.answer(&command, &answer)
+                    },
+                    None => self.adventure.make_decision(&command),
+                };
+                self.say_output(&output);
+            } else if self.adventure.get_locked_decisions().contains(&command) {
+                self.out.say("You can't do that yet.");
+                if let Some(hint) = &self.adventure.questbook.decisions.get(&command).unwrap().locked_text {
+                    self.out.say(&self.adventure.fill_template(hint));
+                }
             } else {
-                println!("Invalid action: {}", command);
+                self.out.say(&format!("Invalid action: {}", command));
             }
 
         }
         if self.dev_mode {
-            println!("{:#?}", self.adventure.log);
+            self.out.say(&format!("{:#?}", self.adventure.log));
         }
-        println!("Game over");
+        self.out.say("Game over");
     }
 
-    fn changed_chapter(&mut self) {
+    /**
+     * Ask the player to create their character, until what they chose is allowed. Returns
+     * false if they stopped before finishing.
+     */
+    fn create_character(&mut self) -> bool {
+        loop {
+            let character = match self.ask_character() {
+                Some(character) => character,
+                None => return false,
+            };
+            match self.adventure.create_character(character) {
+                Ok(()) => return true,
+                Err(error) => self.out.say(&format!("{}\n", error)),
+            }
+        }
+    }
 
+    fn ask_character(&mut self) -> Option<PlayerCharacter> {
+        let creation = self.adventure.questbook.story.creation.as_ref()?;
+        let mut character = PlayerCharacter::default();
+        if creation.name.is_some() {
+            self.out.say("What's your name?");
+            character.name = Some(self.out.read_text()?);
+        }
+        for choice in creation.choices.iter().flatten() {
+            self.out.say(&choice.question);
+            for (index, option) in choice.options.iter().enumerate() {
+                match &option.description {
+                    Some(description) => self.out.say(&format!("{}. {} ({}) - {}", index + 1, option.text, option.id, description)),
+                    None => self.out.say(&format!("{}. {} ({})", index + 1, option.text, option.id)),
+                }
+            }
+            loop {
+                let answer = self.out.read()?;
+                let picked = match answer.parse::<usize>() {
+                    Ok(number) => choice.options.get(number.wrapping_sub(1)),
+                    Err(_) => choice.options.iter().find(|o| o.id == answer),
+                };
+                match picked {
+                    Some(option) => {
+                        character.choices.insert(choice.id.clone(), option.id.clone());
+                        break;
+                    },
+                    None => self.out.say(&format!("Enter a number from 1 to {}", choice.options.len())),
+                }
+            }
+        }
+        if let Some(points) = &creation.points {
+            let mut left = points.total;
+            for counter in points.counters.iter() {
+                let name = self.adventure.questbook.counters.get(counter).map(|c| c.name.as_str()).unwrap_or(counter);
+                let most = points.max.map_or(left, |max| max.min(left));
+                loop {
+                    self.out.say(&format!("Points for {} (0-{}, {} left):", name, most, left));
+                    match self.out.read()?.parse::<u32>() {
+                        Ok(spent) if spent <= most => {
+                            left -= spent;
+                            character.points.insert(counter.clone(), spent);
+                            break;
+                        },
+                        _ => self.out.say(&format!("Enter a number from 0 to {}", most)),
+                    }
+                }
+            }
+        }
+        self.out.say("");
+        Some(character)
     }
 
+    /**
+     * Show the output of something the player did. The adventure includes the heading of
+     * any chapter the player entered, so it isn't shown again.
+     */
+    fn say_output(&mut self, output: &[String]) {
+        self.out.say_all(output);
+        self.shown_chapter = self.adventure.chapter.clone();
+    }
+
+    /**
+     * Show the title and intro of the chapter the player is in.
+     */
+    fn changed_chapter(&mut self) {
+        self.shown_chapter = self.adventure.chapter.clone();
+        if let Some(chapter) = &self.shown_chapter {
+            let heading = self.adventure.chapter_heading(chapter);
+            self.out.say_all(&heading);
+        }
+    }
+
+    #[allow(dead_code)]
     fn changed_scene(&mut self) {
         // Write scene text
 
@@ -94,48 +434,312 @@ impl TextRunner {
 
     fn display_scene(&mut self) {
         let scene = self.adventure.get_scene().unwrap().clone();
-        println!("_{}_\n", self.adventure.questbook.scenes.get(&scene).unwrap().name);
-        println!("{}\n", self.adventure.questbook.scenes.get(&scene).unwrap().background);
+        self.out.say(&format!("_{}_\n", self.adventure.questbook.scenes.get(&scene).unwrap().name));
+        let background = self.adventure.fill_template(&self.adventure.questbook.scenes.get(&scene).unwrap().background);
+        self.out.say(&format!("{}\n", background));
 
         for decision in self.adventure.get_decisions().iter() {
-            if self.adventure.questbook.decisions.get(decision).unwrap().description.is_some() {
-                println!("{}", self.adventure.questbook.decisions.get(decision).unwrap().description.as_ref().unwrap());
+            if let Some(description) = &self.adventure.questbook.decisions.get(decision).unwrap().description {
+                self.out.say(&self.adventure.fill_template(description));
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    fn make_decision(&mut self, _decision: &str) {
+
+    }
+
+    /**
+     * Show the ending reached, the choices made on the way, and the endings discovered over
+     * every playthrough.
+     */
+    fn display_ending(&mut self) {
+        self.record_profile();
+        let questbook = &self.adventure.questbook;
+        let mut text = Vec::new();
+        match self.adventure.ending().and_then(|id| questbook.endings.get(id)) {
+            Some(ending) => {
+                text.push(format!("*** {} ***", ending.title));
+                text.push(self.adventure.fill_template(&ending.epilogue));
+                let kind = ending.kind.as_ref().map(|kind| format!("{:?} ending", kind));
+                let score = ending.score.map(|score| format!("score {}", score));
+                let details: Vec<String> = kind.into_iter().chain(score).collect();
+                if !details.is_empty() {
+                    text.push(details.join(" - "));
+                }
+            },
+            None => text.push("The End".to_string()),
+        }
+        self.out.say_all(&text);
+
+        if !self.adventure.decisions_made.is_empty() {
+            let mut choices = vec!["Your choices:".to_string()];
+            for decision in self.adventure.decisions_made.iter() {
+                let text = questbook.decisions.get(decision).map(|d| d.decision.as_str()).unwrap_or(decision);
+                choices.push(format!("- {}", text));
+            }
+            self.out.say_all(&choices);
+        }
+
+        if questbook.endings.is_empty() {
+            return;
+        }
+        let endings: Vec<&String> = self.profile.endings.iter()
+            .filter_map(|id| questbook.endings.get(id))
+            .map(|ending| &ending.title)
+            .collect();
+        let mut discovered = vec![format!("Endings discovered: {} of {}", endings.len(), questbook.endings.len())];
+        discovered.extend(endings.iter().map(|title| format!("- {}", title)));
+        self.out.say_all(&discovered);
+    }
+
+    /**
+     * Save what the player has achieved to their profile, if there's anything new.
+     */
+    fn record_profile(&mut self) {
+        if !self.profile.record(&self.adventure) {
+            return;
+        }
+        if let Err(error) = write_profile(&self.save_dir, self.adventure.questbook.title(), &self.profile) {
+            self.out.fail(error);
+        }
+    }
+
+    /**
+     * Show the journal, grouped by chapter.
+     */
+    fn display_journal(&mut self) {
+        if self.adventure.journal.is_empty() {
+            self.out.say("Your journal is empty.");
+            return;
+        }
+        let mut text = Vec::new();
+        let mut chapter = None;
+        for entry in self.adventure.journal.iter() {
+            if chapter != Some(&entry.chapter) {
+                if chapter.is_some() {
+                    text.push(String::new());
+                }
+                chapter = Some(&entry.chapter);
+                let title = entry.chapter.as_ref()
+                    .and_then(|c| self.adventure.questbook.chapters.get(c))
+                    .map(|c| c.title.as_str())
+                    .unwrap_or("Before it all began");
+                text.push(format!("~ {} ~", title));
             }
+            text.push(format!("- {}", entry.text));
+        }
+        self.out.say_all(&text);
+    }
+
+    /**
+     * Remind the player of the last things that happened, after loading a game.
+     */
+    fn display_recap(&mut self) {
+        let journal = &self.adventure.journal;
+        if journal.is_empty() {
+            return;
+        }
+        let mut text = vec!["Previously...".to_string()];
+        text.extend(journal[journal.len().saturating_sub(RECAP_ENTRIES)..].iter().map(|entry| entry.text.clone()));
+        self.out.say_all(&text);
+    }
+
+    fn display_achievements(&mut self) {
+        let mut achievements: Vec<(&String, &Achievement)> = self.adventure.questbook.achievements.iter().collect();
+        if achievements.is_empty() {
+            self.out.say("There are no achievements in this adventure.");
+            return;
+        }
+        achievements.sort_by_key(|(id, _)| *id);
+        let mut text = Vec::new();
+        for (id, achievement) in achievements {
+            let mark = if self.adventure.achievements.contains(id) { "x" } else { " " };
+            text.push(format!("[{}] {} - {}", mark, achievement.title, achievement.description));
+        }
+        self.out.say_all(&text);
+    }
+
+    fn display_quests(&mut self) {
+        let mut quests: Vec<(&String, &QuestState)> = self.adventure.quests.iter().collect();
+        if quests.is_empty() {
+            self.out.say("You have no quests.");
+            return;
+        }
+        quests.sort_by_key(|(id, state)| (**state != QuestState::Active, *id));
+        let mut text = Vec::new();
+        for (id, state) in quests {
+            let quest = &self.adventure.questbook.quests[id];
+            let text_state = match state {
+                QuestState::Active => format!("[ ] {} - {}", quest.title, quest.description),
+                QuestState::Completed => format!("[x] {} - Completed", quest.title),
+                QuestState::Failed => format!("[-] {} - Failed", quest.title),
+            };
+            text.push(text_state);
         }
+        self.out.say_all(&text);
     }
 
-    fn make_decision(&mut self, decision: &str) {
+    fn save_game(&mut self, name: &str) {
+        let name = if name.is_empty() { "quicksave" } else { name };
+        match write_save(&self.save_dir, name, &self.adventure) {
+            Ok(path) => self.out.say(&format!("Game saved to {}", path.display())),
+            Err(error) => self.out.fail(error),
+        }
+    }
 
+    fn load_game(&mut self, name: &str) {
+        let name = if name.is_empty() { "quicksave" } else { name };
+        match read_save(&self.save_dir, name) {
+            Ok(save) => {
+                for change in self.adventure.restore(save) {
+                    self.out.say(&change);
+                }
+                self.out.say(&format!("Game {} loaded\n", name));
+                self.display_recap();
+            },
+            Err(error) => self.out.say(&error),
+        }
     }
 
     fn display_help(&mut self) {
-        println!("Type the key of the decision you'd like to make");
-        println!("exit - Exit the game");
+        self.out.say("Type the key of the decision you'd like to make");
+        self.out.say("use <item> - Use an item");
+        self.out.say("combine <item> <item> - Combine two items");
+        self.out.say("drop <item> - Drop an item");
+        self.out.say("journal - Read what has happened so far");
+        self.out.say("quests - List your quests");
+        self.out.say("hint - Get a hint if you're stuck");
+        self.out.say("achievements - List the achievements");
+        self.out.say("save [name] - Save the game");
+        self.out.say("load [name] - Load a saved game");
+        self.out.say("exit - Exit the game");
+        if self.dev_mode {
+            self.out.say("why <id> - DEV - Explain why a chapter, scene, decision, or trigger is available");
+            self.out.say("give <item> / take <item> - DEV - Add or remove an item");
+            self.out.say("tag <id> / untag <id> - DEV - Add or remove a tag");
+            self.out.say("set <counter> <n> - DEV - Set a counter");
+            self.out.say("status +<id> / status -<id> - DEV - Add or remove a status");
+            self.out.say("goto <chapter>/<scene> - DEV - Move to a scene");
+            self.out.say("fire <trigger> - DEV - Apply a trigger's consequences");
+            self.out.say("apply <consequence> - DEV - Apply a consequence");
+            self.out.say("reload - DEV - Reload the questbook file");
+        }
+    }
+
+    /**
+     * Run a developer command. Returns false if the command isn't a developer command.
+     */
+    fn dev_command(&mut self, command: &str) -> bool {
+        let (name, args) = match command.split_once(' ') {
+            Some((name, args)) => (name, args.trim()),
+            None => (command, ""),
+        };
+        let done = match name {
+            "reload" => {
+                self.check_reload(true);
+                return true;
+            },
+            "why" => {
+                self.display_explanation(args);
+                return true;
+            },
+            "give" => self.adventure.give_item(args),
+            "take" => self.adventure.take_item(args),
+            "tag" => self.adventure.add_tag(args),
+            "untag" => self.adventure.remove_tag(args),
+            "set" => match args.split_once(' ') {
+                Some((counter, value)) => match value.trim().parse() {
+                    Ok(value) => self.adventure.set_counter(counter, value),
+                    Err(_) => false,
+                },
+                None => false,
+            },
+            "status" => match (args.strip_prefix('+'), args.strip_prefix('-')) {
+                (Some(status), _) => self.adventure.add_status(status),
+                (_, Some(status)) => self.adventure.remove_status(status),
+                _ => false,
+            },
+            "goto" => match args.split_once('/') {
+                Some((chapter, scene)) => self.adventure.goto(chapter, scene),
+                None => false,
+            },
+            "fire" => match self.adventure.fire_trigger(args) {
+                Some(output) => {
+                    self.say_output(&output);
+                    true
+                },
+                None => false,
+            },
+            "apply" => match self.adventure.apply(args) {
+                Some(output) => {
+                    self.say_output(&output);
+                    true
+                },
+                None => false,
+            },
+            _ => return false,
+        };
+        if done {
+            self.out.say(&format!("DEV - Done: {}", command));
+        } else {
+            self.out.say(&format!("DEV - Failed: {}", command));
+        }
+        true
+    }
+
+    fn display_explanation(&mut self, id: &str) {
+        match self.adventure.explain(id) {
+            Some(explanation) => {
+                self.out.say(&format!("DEV - Why {} {}:", explanation.kind, explanation.id));
+                for note in explanation.context.iter() {
+                    self.out.say(&format!("  {}", note));
+                }
+                for check in explanation.requirements.iter() {
+                    self.out.say(&format!("  {} {}: {}", check.requirement, if check.passed { "passed" } else { "failed" }, check.current));
+                }
+            },
+            None => self.out.say(&format!("DEV - Nothing found with ID {}", id)),
+        }
     }
 
     fn display_inventory(&mut self) {
-        println!("Inventory:");
-        for item in self.adventure.inventory.iter() {
-            println!("{}", item);
+        self.out.say("Inventory:");
+        for (item, count) in self.adventure.inventory.iter() {
+            let definition = self.adventure.questbook.items.get(item).unwrap();
+            if *count > 1 {
+                self.out.say(&format!("{} x{} ({})", definition.name, count, item));
+            } else {
+                self.out.say(&format!("{} ({})", definition.name, item));
+            }
+            self.out.say(&format!("  {}", definition.description));
         }
     }
 
     fn display_scenes(&mut self) {
-        println!("Available scenes:");
+        self.out.say("Available scenes:");
         let cur_scenes = self.adventure.get_scenes();
         for scene in cur_scenes.iter() {
-            println!("{}", scene);
+            self.out.say(scene);
         }
     }
 
     fn display_decisions(&mut self) {
-        println!("Available decisions:");
+        self.out.say("Available decisions:");
         for decision in self.adventure.get_decisions().iter() {
-            println!("{} ({})", self.adventure.questbook.decisions.get(decision).unwrap().decision, decision);
+            self.out.say(&format!("{} ({})", self.adventure.questbook.decisions.get(decision).unwrap().decision, decision));
+        }
+        for decision in self.adventure.get_locked_decisions().iter() {
+            let definition = self.adventure.questbook.decisions.get(decision).unwrap();
+            match &definition.locked_text {
+                Some(hint) => self.out.say(&format!("[locked] {} ({}) - {}", definition.decision, decision, self.adventure.fill_template(hint))),
+                None => self.out.say(&format!("[locked] {} ({})", definition.decision, decision)),
+            }
         }
-        println!("Look around for other paths (look)");
-        println!("Check your inventory (inventory)");
-        println!("Exit the game (exit or quit)");
-        println!("");
+        self.out.say("Look around for other paths (look)");
+        self.out.say("Check your inventory (inventory)");
+        self.out.say("Exit the game (exit or quit)");
+        self.out.say("");
     }
 }
\ No newline at end of file
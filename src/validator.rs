@@ -0,0 +1,407 @@
+use crate::book::*;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+
+/// A problem found in a questbook.
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    /// The questbook can't be played correctly.
+    Error(String),
+    /// The questbook can be played, but probably doesn't do what the author intended.
+    Warning(String),
+}
+
+impl Issue {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Issue::Error(_))
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Error(message) => write!(f, "error: {}", message),
+            Issue::Warning(message) => write!(f, "warning: {}", message),
+        }
+    }
+}
+
+/**
+ * Check a questbook for problems, sorted so the output is stable.
+ */
+pub fn validate(questbook: &Questbook) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    check_references(questbook, &mut issues);
+    check_costs(questbook, &mut issues);
+    check_recipes(questbook, &mut issues);
+    check_resets(questbook, &mut issues);
+    check_prompts(questbook, &mut issues);
+    check_variables(questbook, &mut issues);
+    issues.sort_by_key(|issue| issue.to_string());
+    issues
+}
+
+/**
+ * Report IDs that are referenced but never defined.
+ */
+fn check_references(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    let mut missing = |kind: &str, id: &str, from: String| {
+        issues.push(Issue::Error(format!("{} refers to {} {}, which doesn't exist", from, kind, id)));
+    };
+
+    for chapter in questbook.story.chapters.iter() {
+        if !questbook.chapters.contains_key(chapter) {
+            missing("chapter", chapter, "Story".to_string());
+        }
+    }
+    for decision in questbook.story.decisions.iter().flatten() {
+        if !questbook.decisions.contains_key(decision) {
+            missing("decision", decision, "Story".to_string());
+        }
+    }
+    let points = questbook.story.creation.as_ref().and_then(|c| c.points.as_ref());
+    for counter in points.iter().flat_map(|p| p.counters.iter()) {
+        if !questbook.counters.contains_key(counter) {
+            missing("counter", counter, "Character creation".to_string());
+        }
+    }
+    for (id, chapter) in questbook.chapters.iter() {
+        for scene in chapter.scenes.iter() {
+            if !questbook.scenes.contains_key(scene) {
+                missing("scene", scene, format!("Chapter {}", id));
+            }
+        }
+        for counter in requirement_counters(chapter.requirements.as_deref()) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Chapter {}", id));
+            }
+        }
+        for consequence in chapter.on_enter.iter().flatten().chain(hint_costs(chapter.hints.as_deref())) {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Chapter {}", id));
+            }
+        }
+        for counter in hint_counters(chapter.hints.as_deref()) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Chapter {}", id));
+            }
+        }
+    }
+    for (id, scene) in questbook.scenes.iter() {
+        for decision in scene.decisions.iter() {
+            if !questbook.decisions.contains_key(decision) {
+                missing("decision", decision, format!("Scene {}", id));
+            }
+        }
+        for counter in requirement_counters(scene.requirements.as_deref()) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Scene {}", id));
+            }
+        }
+        let hooks = scene.on_enter.iter().flatten().chain(scene.on_exit.iter().flatten());
+        for consequence in hooks.chain(hint_costs(scene.hints.as_deref())) {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Scene {}", id));
+            }
+        }
+        for counter in hint_counters(scene.hints.as_deref()) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Scene {}", id));
+            }
+        }
+    }
+    for (id, decision) in questbook.decisions.iter() {
+        let mut consequences: Vec<&String> = decision.consequences.iter().collect();
+        consequences.extend(decision.outcomes.iter().flatten().flat_map(|o| o.consequences.iter()));
+        if let Some(check) = &decision.check {
+            consequences.extend(check.success.iter().chain(check.failure.iter()));
+            for counter in check.counters.iter().flatten() {
+                if !questbook.counters.contains_key(counter) {
+                    missing("counter", counter, format!("Decision {}", id));
+                }
+            }
+        }
+        if let Some(prompt) = &decision.prompt {
+            consequences.extend(prompt.success.iter().chain(prompt.failure.iter()));
+        }
+        for consequence in consequences {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Decision {}", id));
+            }
+        }
+        for counter in requirement_counters(decision.requirements.as_deref()) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Decision {}", id));
+            }
+        }
+    }
+    for (id, consequence) in questbook.consequences.iter() {
+        if let Some(scene) = &consequence.scene {
+            if !questbook.scenes.contains_key(scene) {
+                missing("scene", scene, format!("Consequence {}", id));
+            }
+        }
+        if let Some(ending) = &consequence.ending {
+            if !questbook.endings.contains_key(ending) {
+                missing("ending", ending, format!("Consequence {}", id));
+            }
+        }
+        for (scheduled, _) in consequence.schedule.iter().flatten() {
+            if !questbook.consequences.contains_key(scheduled) {
+                missing("consequence", scheduled, format!("Consequence {}", id));
+            }
+        }
+        let counters = consequence.counter.iter().map(|(c, _)| c).chain(consequence.roll.iter().map(|(c, _)| c));
+        for counter in counters {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Consequence {}", id));
+            }
+        }
+    }
+    for (id, item) in questbook.items.iter() {
+        for consequence in item.on_use.iter().flatten() {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Item {}", id));
+            }
+        }
+    }
+    for (id, recipe) in questbook.recipes.iter() {
+        for item in recipe.ingredients.iter() {
+            if !questbook.items.contains_key(item) {
+                missing("item", item, format!("Recipe {}", id));
+            }
+        }
+        for consequence in recipe.consequences.iter() {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Recipe {}", id));
+            }
+        }
+    }
+    for (id, trigger) in questbook.triggers.iter() {
+        for consequence in trigger.consequences.iter() {
+            if !questbook.consequences.contains_key(consequence) {
+                missing("consequence", consequence, format!("Trigger {}", id));
+            }
+        }
+        for counter in requirement_counters(Some(&trigger.requirements)) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Trigger {}", id));
+            }
+        }
+    }
+    for (id, quest) in questbook.quests.iter() {
+        let counters = requirement_counters(quest.start.as_deref()).into_iter()
+            .chain(requirement_counters(Some(&quest.complete)))
+            .chain(requirement_counters(quest.fail.as_deref()));
+        for counter in counters {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Quest {}", id));
+            }
+        }
+    }
+    for (id, achievement) in questbook.achievements.iter() {
+        for counter in requirement_counters(Some(&achievement.requirements)) {
+            if !questbook.counters.contains_key(counter) {
+                missing("counter", counter, format!("Achievement {}", id));
+            }
+        }
+    }
+}
+
+/**
+ * IDs of the counters used by requirements.
+ */
+fn requirement_counters(requirements: Option<&[Requirement]>) -> Vec<&String> {
+    requirements.into_iter().flatten().filter_map(|r| match r {
+        Requirement::CounterEqual(counter, _) | Requirement::CounterLessThan(counter, _) | Requirement::CounterGreaterThan(counter, _) => Some(counter),
+        _ => None,
+    }).collect()
+}
+
+fn hint_costs(hints: Option<&[Hint]>) -> impl Iterator<Item = &String> {
+    hints.into_iter().flatten().flat_map(|hint| hint.cost.iter().flatten())
+}
+
+fn hint_counters(hints: Option<&[Hint]>) -> Vec<&String> {
+    hints.into_iter().flatten().flat_map(|hint| requirement_counters(hint.requirements.as_deref())).collect()
+}
+
+/**
+ * Warn about costs that remove something no consequence ever provides.
+ */
+fn check_costs(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    let creation = questbook.story.creation.iter()
+        .flat_map(|c| c.choices.iter().flatten())
+        .flat_map(|c| c.options.iter())
+        .flat_map(|o| o.provides.iter().flatten());
+    let provided: HashSet<&String> = questbook.consequences.values()
+        .flat_map(|c| c.provides.iter().flatten())
+        .chain(creation)
+        .collect();
+    for (id, consequence) in questbook.consequences.iter() {
+        for cost in consequence.costs.iter().flatten() {
+            if !provided.contains(cost) {
+                issues.push(Issue::Warning(format!("Consequence {} costs {}, which is never provided", id, cost)));
+            }
+        }
+    }
+}
+
+/**
+ * Report recipes that can't be combined, since the player always combines two items.
+ */
+fn check_recipes(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    for (id, recipe) in questbook.recipes.iter() {
+        if recipe.ingredients.len() != 2 {
+            issues.push(Issue::Error(format!("Recipe {} needs exactly 2 ingredients, but has {}", id, recipe.ingredients.len())));
+        }
+    }
+}
+
+/**
+ * Warn about resetting counters that don't exist.
+ */
+fn check_resets(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    for (id, consequence) in questbook.consequences.iter() {
+        for counter in consequence.reset.iter().flatten() {
+            if !questbook.counters.contains_key(counter) {
+                issues.push(Issue::Warning(format!("Consequence {} resets counter {}, which doesn't exist", id, counter)));
+            }
+        }
+    }
+}
+
+/**
+ * Report prompts with patterns that aren't valid regular expressions, and warn about
+ * prompts that can't accept any answer.
+ */
+fn check_prompts(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    for (id, decision) in questbook.decisions.iter() {
+        let prompt = match &decision.prompt {
+            Some(prompt) => prompt,
+            None => continue,
+        };
+        match prompt.pattern.as_deref().map(Regex::new) {
+            Some(Err(error)) => issues.push(Issue::Error(format!("Decision {} has an invalid pattern: {}", id, error))),
+            None if prompt.answers.is_empty() => issues.push(Issue::Warning(format!("Decision {} has a prompt with no answers or pattern", id))),
+            _ => {},
+        }
+    }
+}
+
+/**
+ * Report variables that are set or required but never declared.
+ */
+fn check_variables(questbook: &Questbook, issues: &mut Vec<Issue>) {
+    let mut requirements: Vec<(String, Option<&[Requirement]>)> = Vec::new();
+    for (id, chapter) in questbook.chapters.iter() {
+        requirements.push((format!("Chapter {}", id), chapter.requirements.as_deref()));
+        for hint in chapter.hints.iter().flatten() {
+            requirements.push((format!("Chapter {}", id), hint.requirements.as_deref()));
+        }
+    }
+    for (id, scene) in questbook.scenes.iter() {
+        requirements.push((format!("Scene {}", id), scene.requirements.as_deref()));
+        for hint in scene.hints.iter().flatten() {
+            requirements.push((format!("Scene {}", id), hint.requirements.as_deref()));
+        }
+    }
+    for (id, decision) in questbook.decisions.iter() {
+        requirements.push((format!("Decision {}", id), decision.requirements.as_deref()));
+    }
+    for (id, trigger) in questbook.triggers.iter() {
+        requirements.push((format!("Trigger {}", id), Some(&trigger.requirements)));
+    }
+    for (id, quest) in questbook.quests.iter() {
+        requirements.push((format!("Quest {}", id), quest.start.as_deref()));
+        requirements.push((format!("Quest {}", id), Some(&quest.complete)));
+        requirements.push((format!("Quest {}", id), quest.fail.as_deref()));
+    }
+    for (id, achievement) in questbook.achievements.iter() {
+        requirements.push((format!("Achievement {}", id), Some(&achievement.requirements)));
+    }
+
+    let mut used: Vec<(String, &String)> = Vec::new();
+    for (from, requirements) in requirements {
+        for requirement in requirements.into_iter().flatten() {
+            if let Requirement::VariableEqual(variable, _) | Requirement::VariableIn(variable, _) = requirement {
+                used.push((from.clone(), variable));
+            }
+        }
+    }
+    for (id, decision) in questbook.decisions.iter() {
+        if let Some(variable) = decision.prompt.as_ref().and_then(|p| p.variable.as_ref()) {
+            used.push((format!("Decision {}", id), variable));
+        }
+    }
+    for (id, consequence) in questbook.consequences.iter() {
+        if let Some((variable, _)) = &consequence.variable {
+            used.push((format!("Consequence {}", id), variable));
+        }
+    }
+    if let Some(variable) = questbook.story.creation.as_ref().and_then(|c| c.name.as_ref()) {
+        used.push(("Character creation".to_string(), variable));
+    }
+    for (from, variable) in used {
+        if !questbook.variables.contains_key(variable) {
+            issues.push(Issue::Error(format!("{} refers to variable {}, which doesn't exist", from, variable)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "
+story:
+  title: Test
+  chapters: [start]
+chapters:
+  start:
+    title: Start
+    scenes: [room]
+scenes:
+  room:
+    name: Room
+    background: A room.
+    decisions: []
+items:
+  herb:
+    name: Herb
+    description: A herb.
+  water:
+    name: Water
+    description: Some water.
+decisions:
+characters:
+consequences:
+  brewed:
+    conclusion: You brew a potion.
+statuses:
+counters:
+triggers:
+recipes:
+  potion:
+    ingredients: [herb, water]
+    consequences: [brewed]
+";
+
+    fn issues(book: &str) -> Vec<Issue> {
+        validate(&serde_yaml::from_str(book).unwrap())
+    }
+
+    #[test]
+    fn recipes_with_two_ingredients_are_valid() {
+        assert_eq!(issues(BOOK), vec![]);
+    }
+
+    #[test]
+    fn recipes_need_exactly_two_ingredients() {
+        let book = BOOK.replace("[herb, water]", "[herb, water, herb]");
+        assert_eq!(issues(&book), vec![Issue::Error("Recipe potion needs exactly 2 ingredients, but has 3".to_string())]);
+        let book = BOOK.replace("[herb, water]", "[herb]");
+        assert_eq!(issues(&book), vec![Issue::Error("Recipe potion needs exactly 2 ingredients, but has 1".to_string())]);
+    }
+}